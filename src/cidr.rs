@@ -0,0 +1,50 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                mask(u32::from(net), u32::from(addr), self.prefix, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                mask(u128::from(net), u128::from(addr), self.prefix, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn mask<T>(net: T, addr: T, prefix: u8, bits: u8) -> bool
+where
+    T: Copy + Eq + std::ops::BitXor<Output = T> + std::ops::Shr<u8, Output = T> + Default,
+{
+    prefix == 0 || (net ^ addr) >> (bits - prefix) == T::default()
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = addr.parse::<IpAddr>().map_err(|e| e.to_string())?;
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|e| e.to_string())?,
+            None => bits,
+        };
+        if prefix > bits {
+            return Err(format!("prefix length {prefix} is too long"));
+        }
+        Ok(Self { addr, prefix })
+    }
+}
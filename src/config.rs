@@ -0,0 +1,168 @@
+mod de;
+
+use crate::{probe, window};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::Duration;
+
+#[serde_with::serde_as]
+#[derive(Clone, Serialize)]
+pub struct Target {
+    pub name: String,
+    // also aggregated on its own under /ready/group/{group}
+    pub group: Option<String>,
+    pub liveness_probe: Option<probe::Probe>,
+    pub readiness_probe: Option<probe::Probe>,
+    pub startup_probe: Option<probe::Probe>,
+    // fail liveness if the startup probe does not succeed in time
+    #[serde_as(as = "Option<serde_with::DurationSecondsWithFrac<f64>>")]
+    pub startup_deadline_seconds: Option<Duration>,
+    // starts probing once every target in the earlier phases has started up
+    pub startup_phase: usize,
+    // report unready outside this window regardless of the probes
+    pub ready_window: Option<window::Window>,
+}
+
+impl Target {
+    pub fn in_ready_window(&self) -> bool {
+        self.ready_window
+            .as_ref()
+            .is_none_or(|window| window.contains(chrono::Local::now().naive_local()))
+    }
+
+    pub fn probe(&self, kind: Kind) -> Option<&probe::Probe> {
+        match kind {
+            Kind::Liveness => self.liveness_probe.as_ref(),
+            Kind::Readiness => self.readiness_probe.as_ref(),
+            Kind::Startup => self.startup_probe.as_ref(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Kind {
+    Liveness,
+    Readiness,
+    Startup,
+}
+
+// e.g.
+// [[target]]
+// name = "app"
+// readiness_probe = { http_get = { port = 8080 } }
+//
+// or, with a .yaml or .yml extension,
+// target:
+//   - name: app
+//     readiness_probe: { http_get: { port: 8080 } }
+#[derive(Deserialize)]
+struct Config {
+    #[serde(default)]
+    target: Vec<serde_json::Value>,
+}
+
+// each target along with its config as written, to tell which ones changed on reload
+pub fn load(path: &Path) -> anyhow::Result<Vec<(Target, serde_json::Value)>> {
+    let paths = if path.is_dir() {
+        let mut paths = std::fs::read_dir(path)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| Ok(entry?.path()))
+                    .collect::<std::io::Result<Vec<_>>>()
+            })
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        paths.retain(|path| {
+            path.is_file()
+                && path.extension().is_some_and(|extension| {
+                    ["toml", "yaml", "yml"].map(OsStr::new).contains(&extension)
+                })
+        });
+        paths.sort();
+        paths
+    } else {
+        vec![path.to_owned()]
+    };
+
+    let mut names = HashMap::new();
+    let mut targets = Vec::new();
+    for path in paths {
+        for (target, value) in load_file(&path)? {
+            if let Some(previous) = names.insert(target.name.clone(), path.clone()) {
+                if previous == path {
+                    anyhow::bail!("duplicate target {} in {}", target.name, path.display());
+                } else {
+                    anyhow::bail!(
+                        "duplicate target {} in {} and {}",
+                        target.name,
+                        previous.display(),
+                        path.display(),
+                    );
+                }
+            }
+            targets.push((target, value));
+        }
+    }
+    Ok(targets)
+}
+
+fn load_file(path: &Path) -> anyhow::Result<Vec<(Target, serde_json::Value)>> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let data = expand(&data, |name| std::env::var(name).ok())
+        .with_context(|| format!("failed to expand {}", path.display()))?;
+    let config = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::from_str::<Config>(&data).map_err(anyhow::Error::from),
+        _ => toml::from_str::<Config>(&data).map_err(anyhow::Error::from),
+    }
+    .with_context(|| format!("failed to parse {}", path.display()))?;
+    config
+        .target
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let target = Target::deserialize(&value).with_context(|| {
+                match value.get("name").and_then(serde_json::Value::as_str) {
+                    Some(name) => format!("target {name} in {}", path.display()),
+                    None => format!("target[{i}] in {}", path.display()),
+                }
+            })?;
+            Ok((target, value))
+        })
+        .collect()
+}
+
+// replaces ${VAR} and ${VAR:-default} with values from `lookup`, and $$ with $
+pub fn expand(data: &str, lookup: impl Fn(&str) -> Option<String>) -> anyhow::Result<String> {
+    let mut expanded = String::with_capacity(data.len());
+    let mut rest = data;
+    while let Some(i) = rest.find('$') {
+        expanded.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if let Some(r) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('{') {
+            let Some(end) = r.find('}') else {
+                anyhow::bail!("unterminated ${{");
+            };
+            let (name, default) = match r[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&r[..end], None),
+            };
+            match lookup(name) {
+                Some(value) => expanded.push_str(&value),
+                None => expanded.push_str(
+                    default.with_context(|| format!("environment variable {name} is not set"))?,
+                ),
+            }
+            rest = &r[end + 1..];
+        } else {
+            expanded.push('$');
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
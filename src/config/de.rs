@@ -0,0 +1,67 @@
+use crate::{probe, window};
+use serde::{Deserialize, Deserializer};
+use std::time::Duration;
+
+impl<'de> Deserialize<'de> for super::Target {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[serde_with::serde_as]
+        #[derive(Deserialize)]
+        struct Target {
+            name: String,
+            group: Option<String>,
+            // shared by the probes that do not specify their own method
+            method: Option<probe::Method>,
+            liveness_probe: Option<probe::Partial>,
+            readiness_probe: Option<probe::Partial>,
+            startup_probe: Option<probe::Partial>,
+            #[serde_as(as = "Option<serde_with::DurationSecondsWithFrac<f64>>")]
+            startup_deadline_seconds: Option<Duration>,
+            #[serde(default)]
+            startup_phase: usize,
+            ready_window: Option<window::Window>,
+        }
+
+        let value = Target::deserialize(deserializer)?;
+        let method = value.method.as_ref();
+        // names the probe at fault
+        let complete = |name, probe: Option<probe::Partial>| {
+            probe
+                .map(|probe| {
+                    probe
+                        .complete(method)
+                        .map_err(|e: D::Error| serde::de::Error::custom(format!("{name}: {e}")))
+                })
+                .transpose()
+        };
+        let liveness_probe = complete("liveness_probe", value.liveness_probe)?;
+        let readiness_probe = complete("readiness_probe", value.readiness_probe)?;
+        let startup_probe = complete("startup_probe", value.startup_probe)?;
+        // as in Kubernetes, only readiness may wait for several successes
+        for (name, probe) in [
+            ("liveness_probe", &liveness_probe),
+            ("startup_probe", &startup_probe),
+        ] {
+            if probe
+                .as_ref()
+                .is_some_and(|probe| probe.success_threshold != 1)
+            {
+                return Err(serde::de::Error::custom(format!(
+                    "success_threshold of {name} must be 1"
+                )));
+            }
+        }
+        Ok(Self {
+            name: value.name,
+            group: value.group,
+            liveness_probe,
+            readiness_probe,
+            startup_probe,
+            startup_deadline_seconds: value.startup_deadline_seconds,
+            startup_phase: value.startup_phase,
+            ready_window: value.ready_window,
+        })
+    }
+}
@@ -1,30 +1,304 @@
-use hyper_rustls::ConfigBuilderExt;
-use std::sync::Arc;
+use anyhow::Context as _;
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt, TryFutureExt};
+use hyper_util::client::legacy::connect::dns::{GaiResolver, Name};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
-pub fn tls_config() -> Result<rustls::ClientConfig, rustls::Error> {
-    Ok(rustls::ClientConfig::builder_with_provider(Arc::new(
+// trusts the certificates in `ca_certs`, in addition to the public roots unless disabled,
+// and presents the PEM certificate chain and key of `client_auth` to servers asking for one
+pub fn tls_config(
+    ca_certs: &[PathBuf],
+    default_roots: bool,
+    client_auth: Option<(&Path, &Path)>,
+) -> anyhow::Result<rustls::ClientConfig> {
+    let mut roots = rustls::RootCertStore::empty();
+    if default_roots {
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    }
+    for ca in ca_certs {
+        let (added, _) = roots.add_parsable_certificates(
+            CertificateDer::pem_file_iter(ca)?.collect::<Result<Vec<_>, _>>()?,
+        );
+        if added == 0 {
+            anyhow::bail!("no certificates found in {}", ca.display());
+        }
+    }
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::new(
         rustls::crypto::aws_lc_rs::default_provider(),
     ))
     .with_safe_default_protocol_versions()?
-    .with_webpki_roots()
-    .with_no_client_auth())
+    .with_root_certificates(roots);
+    match client_auth {
+        Some((cert, key)) => {
+            let certs = CertificateDer::pem_file_iter(cert)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .with_context(|| format!("failed to read {}", cert.display()))?;
+            if certs.is_empty() {
+                anyhow::bail!("no certificates found in {}", cert.display());
+            }
+            let key = PrivateKeyDer::from_pem_file(key)
+                .with_context(|| format!("failed to read {}", key.display()))?;
+            Ok(builder.with_client_auth_cert(certs, key)?)
+        }
+        None => Ok(builder.with_no_client_auth()),
+    }
 }
 
+// accepts any server certificate, still checking that the handshake is signed by its key
+pub fn insecure(mut tls_config: rustls::ClientConfig) -> rustls::ClientConfig {
+    #[derive(Debug)]
+    struct NoVerification(Arc<rustls::crypto::CryptoProvider>);
+
+    impl rustls::client::danger::ServerCertVerifier for NoVerification {
+        fn verify_server_cert(
+            &self,
+            _: &CertificateDer<'_>,
+            _: &[CertificateDer<'_>],
+            _: &rustls::pki_types::ServerName<'_>,
+            _: &[u8],
+            _: rustls::pki_types::UnixTime,
+        ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+            Ok(rustls::client::danger::ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            rustls::crypto::verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            rustls::crypto::verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+
+    let provider = tls_config.crypto_provider().clone();
+    tls_config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(NoVerification(provider)));
+    tls_config
+}
+
+pub fn server_tls_config(cert: &Path, key: &Path) -> anyhow::Result<rustls::ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(cert)?.collect::<Result<_, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(key)?;
+    let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::aws_lc_rs::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+// how the addresses of a host resolving to several are tried
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum ConnectOrder {
+    // https://datatracker.ietf.org/doc/html/rfc8305
+    #[default]
+    HappyEyeballs,
+    // one after another in the order given by the resolver
+    Ordered,
+}
+
+#[derive(
+    Clone, Copy, Debug, Default, Eq, Hash, PartialEq, serde::Deserialize, serde::Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum HttpVersion {
+    // negotiated by ALPN over TLS, HTTP/1.1 over cleartext
+    #[default]
+    Auto,
+    Http1,
+    // with prior knowledge over cleartext, as gRPC requires
+    Http2,
+}
+
+// head start of each attempt before racing the next address, as in hyper
+const HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(300);
+
 pub type Client<B> = hyper_util::client::legacy::Client<
-    hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>,
+    hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector<Resolver>>,
     B,
 >;
-pub fn client<B>(tls_config: rustls::ClientConfig) -> Client<B>
+pub fn client<B>(
+    tls_config: rustls::ClientConfig,
+    resolver: Resolver,
+    connect_timeout: Option<Duration>,
+    connect_order: ConnectOrder,
+    version: HttpVersion,
+) -> Client<B>
 where
     B: http_body::Body + Send,
     B::Data: Send,
 {
-    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+    let mut http = hyper_util::client::legacy::connect::HttpConnector::new_with_resolver(resolver);
+    http.enforce_http(false);
+    http.set_connect_timeout(connect_timeout);
+    http.set_happy_eyeballs_timeout(match connect_order {
+        ConnectOrder::HappyEyeballs => Some(HAPPY_EYEBALLS_DELAY),
+        ConnectOrder::Ordered => None,
+    });
+    let builder = hyper_rustls::HttpsConnectorBuilder::new()
         .with_tls_config(tls_config)
-        .https_or_http()
-        .enable_http1()
-        .enable_http2()
-        .build();
+        .https_or_http();
+    let connector = match version {
+        HttpVersion::Auto => builder.enable_http1().enable_http2().wrap_connector(http),
+        HttpVersion::Http1 => builder.enable_http1().wrap_connector(http),
+        HttpVersion::Http2 => builder.enable_http2().wrap_connector(http),
+    };
     hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+        .http2_only(version == HttpVersion::Http2)
         .build(connector)
 }
+
+// resolves with getaddrinfo, reusing the addresses for `ttl` when set
+#[derive(Clone)]
+pub struct Resolver {
+    gai: GaiResolver,
+    ttl: Option<Duration>,
+    cache: Arc<Mutex<HashMap<Name, Resolved>>>,
+}
+
+type Resolved = (Instant, Vec<SocketAddr>);
+
+impl Resolver {
+    pub fn new(ttl: Option<Duration>) -> Self {
+        Self {
+            gai: GaiResolver::new(),
+            ttl,
+            cache: Arc::default(),
+        }
+    }
+}
+
+impl tower::Service<Name> for Resolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<Self::Response>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let Some(ttl) = self.ttl else {
+            return self
+                .gai
+                .call(name)
+                .map_ok(|addrs| addrs.collect::<Vec<_>>().into_iter())
+                .boxed();
+        };
+        if let Some((resolved, addrs)) = self.cache.lock().unwrap().get(&name)
+            && resolved.elapsed() < ttl
+        {
+            return futures::future::ready(Ok(addrs.clone().into_iter())).boxed();
+        }
+        let mut gai = self.gai.clone();
+        let cache = self.cache.clone();
+        async move {
+            let addrs = gai.call(name.clone()).await?.collect::<Vec<_>>();
+            cache
+                .lock()
+                .unwrap()
+                .insert(name, (Instant::now(), addrs.clone()));
+            Ok(addrs.into_iter())
+        }
+        .boxed()
+    }
+}
+
+// for probes speaking plain TCP, connecting like the HTTP client does
+pub async fn connect(
+    resolver: &Resolver,
+    host: &str,
+    port: u16,
+    connect_order: ConnectOrder,
+) -> io::Result<tokio::net::TcpStream> {
+    use tower::Service;
+
+    let name = host.parse::<Name>().map_err(io::Error::other)?;
+    let mut addrs = resolver
+        .clone()
+        .call(name)
+        .await?
+        .map(|addr| SocketAddr::new(addr.ip(), port))
+        .collect::<Vec<_>>();
+    let mut last_error = None;
+    match connect_order {
+        ConnectOrder::Ordered => {
+            for addr in addrs {
+                match tokio::net::TcpStream::connect(addr).await {
+                    Ok(stream) => return Ok(stream),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+        }
+        ConnectOrder::HappyEyeballs => {
+            // alternates between the address families, starting with the preferred one
+            if let Some(first) = addrs.first().copied() {
+                let (preferred, fallback) = addrs
+                    .into_iter()
+                    .partition::<Vec<_>, _>(|addr| addr.is_ipv6() == first.is_ipv6());
+                addrs = Vec::with_capacity(preferred.len() + fallback.len());
+                for i in 0..preferred.len().max(fallback.len()) {
+                    addrs.extend(preferred.get(i).copied());
+                    addrs.extend(fallback.get(i).copied());
+                }
+            }
+            let mut addrs = addrs.into_iter();
+            let mut attempts = futures::stream::FuturesUnordered::new();
+            loop {
+                // the next address starts on a failure or once the head start runs out
+                if let Some(addr) = addrs.next() {
+                    attempts.push(tokio::net::TcpStream::connect(addr));
+                }
+                tokio::select! {
+                    Some(output) = attempts.next() => match output {
+                        Ok(stream) => return Ok(stream),
+                        Err(e) => last_error = Some(e),
+                    },
+                    _ = tokio::time::sleep(HAPPY_EYEBALLS_DELAY), if addrs.len() > 0 => {}
+                    else => break,
+                }
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no addresses found for {host}"),
+        )
+    }))
+}
@@ -0,0 +1,99 @@
+use crate::hyper;
+use anyhow::Context as _;
+use bytes::Bytes;
+use std::path::Path;
+use std::time::Duration;
+
+// https://kubernetes.io/docs/tasks/run-application/access-api-from-pod/#directly-accessing-the-rest-api
+const SERVICE_ACCOUNT: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+// https://kubernetes.io/docs/concepts/workloads/pods/pod-lifecycle/#pod-readiness-gate
+pub struct ReadinessGate {
+    client: hyper::Client<http_body_util::Full<Bytes>>,
+    uri: http::Uri,
+    token: String,
+    condition_type: String,
+}
+
+impl ReadinessGate {
+    pub fn in_cluster(condition_type: String) -> anyhow::Result<Self> {
+        let service_account = Path::new(SERVICE_ACCOUNT);
+        let host = std::env::var("KUBERNETES_SERVICE_HOST").context("not in a cluster")?;
+        let port = std::env::var("KUBERNETES_SERVICE_PORT").context("not in a cluster")?;
+        // set through the downward API, or the hostname which defaults to the pod name
+        let pod = std::env::var("POD_NAME")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .context("POD_NAME is not set")?;
+        let namespace = std::fs::read_to_string(service_account.join("namespace"))?;
+        let token = std::fs::read_to_string(service_account.join("token"))?;
+        let tls_config = hyper::tls_config(&[service_account.join("ca.crt")], false, None)?;
+        Ok(Self {
+            client: hyper::client(
+                tls_config,
+                hyper::Resolver::new(None),
+                None,
+                hyper::ConnectOrder::default(),
+                hyper::HttpVersion::Auto,
+            ),
+            uri: format!(
+                "https://{host}:{port}/api/v1/namespaces/{}/pods/{pod}/status",
+                namespace.trim(),
+            )
+            .parse()?,
+            token: token.trim().to_string(),
+            condition_type,
+        })
+    }
+
+    // polls `ready` and patches the pod condition whenever it changes
+    pub async fn run(&self, ready: impl Fn() -> bool) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        let mut patched = None;
+        loop {
+            interval.tick().await;
+            let ready = ready();
+            if patched == Some(ready) {
+                continue;
+            }
+            match self.patch(ready).await {
+                Ok(_) => {
+                    tracing::info!(condition = self.condition_type, ready, "patched pod");
+                    patched = Some(ready);
+                }
+                Err(e) => tracing::warn!(error = e.to_string(), "failed to patch pod"),
+            }
+        }
+    }
+
+    async fn patch(&self, ready: bool) -> anyhow::Result<()> {
+        let body = serde_json::json!({
+            "status": {
+                "conditions": [{
+                    "type": self.condition_type,
+                    "status": if ready { "True" } else { "False" },
+                    "lastTransitionTime": chrono::Utc::now()
+                        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                }],
+            },
+        });
+        let mut request = http::Request::new(http_body_util::Full::new(Bytes::from(
+            serde_json::to_vec(&body)?,
+        )));
+        *request.method_mut() = http::Method::PATCH;
+        request.uri_mut().clone_from(&self.uri);
+        request.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("application/strategic-merge-patch+json"),
+        );
+        let mut authorization = http::HeaderValue::try_from(format!("Bearer {}", self.token))?;
+        authorization.set_sensitive(true);
+        request
+            .headers_mut()
+            .insert(http::header::AUTHORIZATION, authorization);
+        let response = self.client.request(request).await?;
+        if !response.status().is_success() {
+            anyhow::bail!("{}", response.status());
+        }
+        Ok(())
+    }
+}
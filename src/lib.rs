@@ -0,0 +1,37 @@
+//! The probe engine of healthzd, for running probes from other binaries.
+//!
+//! Only [`probe`], [`config`] and what their APIs refer to, [`hyper`], [`statsd`] and
+//! [`window`], are public. The state of the targets, the HTTP endpoints and the rest of the
+//! daemon stay in the `healthzd` binary.
+//!
+//! A [`Probe`] is deserialized from the same config as a `*_probe` of a target, and
+//! [`Probe::watch`] yields its [`Status`] whenever it changes, while [`Probe::check`] judges a
+//! single attempt.
+//!
+//! ```
+//! use futures::StreamExt;
+//!
+//! let probe = serde_json::from_value::<healthzd::Probe>(serde_json::json!({
+//!     "exec": {"command": ["true"]},
+//!     "period_seconds": 1,
+//! }))?;
+//! let context = healthzd::Context::new(
+//!     healthzd::hyper::tls_config(&[], true, None)?,
+//!     healthzd::hyper::Resolver::new(None),
+//!     healthzd::hyper::ConnectOrder::default(),
+//! );
+//! let report = healthzd::probe::Report::default();
+//!
+//! let status = tokio::runtime::Runtime::new()?
+//!     .block_on(std::pin::pin!(probe.watch(&context, &report)).next());
+//! assert_eq!(status, Some(healthzd::Status::Success));
+//! # anyhow::Ok(())
+//! ```
+
+pub mod config;
+pub mod hyper;
+pub mod probe;
+pub mod statsd;
+pub mod window;
+
+pub use probe::{Context, Method, Probe, Status};
@@ -0,0 +1,106 @@
+use anyhow::Context as _;
+use std::fmt;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FormatFields, FormattedFields, MakeWriter};
+
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    Text,
+    // one object per line, with the fields of the enclosing spans
+    Json,
+    Compact,
+}
+
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum Level {
+    Trace,
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+// RUST_LOG, when set, takes precedence over `level`
+pub fn filter(level: Level, rust_log: Option<&str>) -> anyhow::Result<EnvFilter> {
+    match rust_log {
+        Some(directives) => EnvFilter::try_new(directives).context("invalid RUST_LOG"),
+        None => Ok(EnvFilter::new(match level {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        })),
+    }
+}
+
+pub fn subscriber<W>(
+    format: Format,
+    filter: EnvFilter,
+    writer: W,
+) -> Box<dyn tracing::Subscriber + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer);
+    match format {
+        Format::Text => Box::new(builder.finish()),
+        Format::Json => Box::new(builder.json().fmt_fields(JsonFields).finish()),
+        Format::Compact => Box::new(builder.compact().finish()),
+    }
+}
+
+// JSON takes `name` for the name of the span, so the `name` field of the target span is written
+// as `target` instead
+struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        write!(writer, "{}", renamed(serde_json::Map::new(), fields)?)
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let map = if current.fields.is_empty() {
+            serde_json::Map::new()
+        } else {
+            serde_json::from_str(&current.fields).map_err(|_| fmt::Error)?
+        };
+        current.fields = renamed(map, fields)?;
+        Ok(())
+    }
+}
+
+fn renamed(
+    mut map: serde_json::Map<String, serde_json::Value>,
+    fields: impl RecordFields,
+) -> Result<String, fmt::Error> {
+    let mut json = String::new();
+    tracing_subscriber::fmt::format::JsonFields::new()
+        .format_fields(Writer::new(&mut json), fields)?;
+    let fields = serde_json::from_str::<serde_json::Map<_, _>>(&json).map_err(|_| fmt::Error)?;
+    for (key, value) in fields {
+        map.insert(
+            if key == "name" {
+                "target".to_string()
+            } else {
+                key
+            },
+            value,
+        );
+    }
+    Ok(serde_json::Value::Object(map).to_string())
+}
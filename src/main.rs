@@ -1,108 +1,837 @@
-mod hyper;
-mod probe;
+mod cidr;
+mod k8s;
+mod log;
+mod privilege;
+mod watchdog;
 
-use axum::{Router, routing};
-use clap::Parser;
+use axum::error_handling::HandleErrorLayer;
+use axum::extract::{ConnectInfo, Path};
+use axum::response::IntoResponse;
+use axum::{Json, Router, routing};
+use clap::{Parser, Subcommand, ValueEnum};
 use futures::{FutureExt, StreamExt};
-use serde::Deserialize;
+use healthzd::config::{self, Kind, Target};
+use healthzd::{hyper, probe, statsd};
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
 use std::io;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::pin;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tower::limit::ConcurrencyLimitLayer;
+use tower_http::set_header::SetResponseHeaderLayer;
+use tower_http::timeout::TimeoutLayer;
 use tracing_futures::Instrument;
+use tracing_subscriber::util::SubscriberInitExt as _;
 
 #[derive(Parser)]
+#[clap(subcommand_negates_reqs = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// Serve everything not moved onto --liveness-bind or --readiness-bind on this address
+    #[clap(
+        long,
+        required_unless_present_any = [
+            "once",
+            "check",
+            "validate",
+            "unix_socket",
+            "liveness_bind",
+            "readiness_bind",
+        ]
+    )]
+    bind: Option<SocketAddr>,
+    /// Run every probe once, print the results and exit with the code of the first failure
+    /// (10: http_get, 11: exec, 12: cert_file, 13: ntp, 14: external,
+    /// 15: env_present or secret_present, 16: tcp_exchange, 17: redis, 18: postgres, 19: fd,
+    /// 20: tcp_socket, 21: grpc, 22: dns)
     #[clap(long)]
-    bind: SocketAddr,
-    #[clap(long, value_parser = parse_target)]
-    target: Vec<Target>,
+    once: bool,
+    /// Run every probe once, ignoring its thresholds, print a PASS/FAIL table and exit with 1 if
+    /// any failed
+    #[clap(long, conflicts_with = "once")]
+    check: bool,
+    /// Only parse --target and --config, print what is wrong with them and exit with 1 if
+    /// anything is, without running any probe
+    #[clap(long, conflicts_with_all = ["once", "check"])]
+    validate: bool,
+    /// Keep retrying for this long while the bind address is in use
+    #[clap(long, value_parser = parse_seconds)]
+    bind_retry_seconds: Option<Duration>,
+    /// Serve /live on this address instead of on --bind, to be firewalled apart from the rest
+    #[clap(long)]
+    liveness_bind: Option<SocketAddr>,
+    /// Serve /ready and /ready/group/{group} on this address instead of on --bind
+    #[clap(long)]
+    readiness_bind: Option<SocketAddr>,
+    /// Also serve on this Unix domain socket, replacing any stale socket file
+    #[clap(long)]
+    unix_socket: Option<PathBuf>,
+    /// Also serve over TLS on this address
+    #[clap(long, requires_all = ["tls_cert", "tls_key"])]
+    tls_bind: Option<SocketAddr>,
+    /// PEM certificate chain for --tls-bind
+    #[clap(long)]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key for --tls-bind
+    #[clap(long)]
+    tls_key: Option<PathBuf>,
+    // parsed after the other arguments, so that --validate reports a broken one like a broken
+    // config file
+    #[clap(long)]
+    target: Vec<String>,
+    /// Also probe the targets listed as [[target]] in this TOML file (YAML with a .yaml or .yml
+    /// extension), or in every such file of this directory, re-read on SIGHUP. ${VAR} and
+    /// ${VAR:-default} are expanded from the environment
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Fail liveness when the probe executor, or the task of any target, stops responding for
+    /// this long, and recover once it responds again
+    #[clap(long, value_parser = parse_interval)]
+    watchdog_interval_seconds: Option<Duration>,
+    /// Respond 503 to requests beyond this many in flight
+    #[clap(long)]
+    max_connections: Option<usize>,
+    /// Respond 408 to requests taking longer than this
+    #[clap(long, value_parser = parse_seconds)]
+    request_timeout_seconds: Option<Duration>,
+    /// Respond to /ready with the readiness and last probe duration of each target
+    #[clap(long)]
+    ready_json: bool,
+    /// Report unlive until the first liveness check succeeds
+    #[clap(long)]
+    liveness_pessimistic: bool,
+    /// Prepend `<PREFIX>.` to the target names
+    #[clap(long)]
+    target_name_prefix: Option<String>,
+    /// Enable the admin endpoints, guarded by the bearer token read from this file
+    #[clap(long)]
+    admin_token_file: Option<PathBuf>,
+    /// Respond 403 to admin requests from outside these networks (e.g. 127.0.0.1/8), except
+    /// those over --unix-socket
+    #[clap(long)]
+    admin_allow_cidr: Vec<cidr::Cidr>,
+    /// Give each target its own HTTP connection pool, so a stuck upstream cannot
+    /// starve the others. Costs memory that grows with the number of targets, as each
+    /// keeps its own idle connections and TLS session cache
+    #[clap(long)]
+    isolate_clients: bool,
+    /// Also trust the CA certificates in this PEM file for HTTPS probes
+    #[clap(long, alias = "ca-file")]
+    ca_cert: Vec<PathBuf>,
+    /// Trust only the certificates given by --ca-cert instead of the public roots
+    #[clap(long, alias = "ca-only", requires = "ca_cert")]
+    no_default_roots: bool,
+    /// Present this PEM certificate chain to servers asking for client authentication
+    #[clap(long, requires = "client_key")]
+    client_cert: Option<PathBuf>,
+    /// PEM private key for --client-cert
+    #[clap(long, requires = "client_cert")]
+    client_key: Option<PathBuf>,
+    /// How to try the addresses of a host resolving to several: racing them with a 300ms head
+    /// start each, alternating between IPv6 and IPv4 (the default), or strictly in resolver order
+    #[clap(long, value_enum, default_value_t)]
+    connect_order: hyper::ConnectOrder,
+    /// Cap each probe attempt, including its retries, at this long or at the start of the next
+    /// period, whichever comes first, unless the probe sets its own budget_seconds
+    #[clap(long, value_parser = parse_seconds)]
+    probe_timeout_budget_seconds: Option<Duration>,
+    /// Reuse resolved addresses of probed hosts for this long, instead of resolving on every connection
+    #[clap(long, value_parser = parse_seconds)]
+    dns_cache_seconds: Option<Duration>,
+    /// Write the liveness and readiness of each target to this file as JSON on every change
+    #[clap(long)]
+    state_output: Option<PathBuf>,
+    /// Patch this condition type of the pod, running in a cluster, whenever the readiness changes
+    #[clap(long)]
+    k8s_readiness_gate: Option<String>,
+    /// Shut down gracefully after running for this long, for the orchestrator to restart
+    #[clap(long, value_parser = parse_seconds)]
+    max_lifetime_seconds: Option<Duration>,
+    /// On SIGTERM, SIGINT or --max-lifetime-seconds, report unready for this long before shutting down
+    #[clap(long, alias = "shutdown-grace", value_parser = parse_seconds)]
+    shutdown_delay_seconds: Option<Duration>,
+    /// Switch to this user (name or uid) after binding, before running any probe
+    #[clap(long)]
+    user: Option<String>,
+    /// Switch to this group (name or gid) after binding, instead of the primary group of --user
+    #[clap(long)]
+    group: Option<String>,
+    /// Send probe metrics over UDP to this StatsD address (host:port)
+    #[clap(long)]
+    statsd: Option<String>,
+    /// Tag metrics DogStatsD-style, or fold the tags into the metric names for plain StatsD
+    #[clap(long, value_enum, default_value_t = statsd::Format::Dogstatsd)]
+    statsd_format: statsd::Format,
+    /// Log as human-readable text, as JSON lines for log pipelines, or as compact text
+    #[clap(long, value_enum, default_value_t)]
+    log_format: log::Format,
+    /// Log events of this level and above, unless overridden by RUST_LOG
+    #[clap(long, value_enum, default_value_t)]
+    log_level: log::Level,
+    /// Report this version of the application in the X-App-Version header of health responses
+    #[clap(long, env = "APP_VERSION")]
+    app_version: Option<http::HeaderValue>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a probe repeatedly and report its latency distribution
+    Bench {
+        /// Config file to look the target up in, besides --config and --target
+        #[clap(long = "probe", alias = "config")]
+        config: Option<PathBuf>,
+        /// Name of the target
+        #[clap(long)]
+        name: String,
+        /// Probe of the target to run, needed only when it has more than one
+        #[clap(long, value_enum)]
+        kind: Option<Kind>,
+        #[clap(long, default_value_t = 100)]
+        count: usize,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
-
+    let started = Instant::now();
     let args = Args::parse();
+    let filter = log::filter(args.log_level, std::env::var("RUST_LOG").ok().as_deref())?;
+    log::subscriber(args.log_format, filter, std::io::stdout).try_init()?;
 
-    let tls_config = hyper::tls_config()?;
-    let context = probe::Context {
-        client: hyper::client(tls_config),
-    };
-
+    let prefix = args.target_name_prefix.as_deref();
     let targets = args
         .target
+        .iter()
+        .map(|s| parse_target(s).map_err(|e| anyhow::anyhow!("--target: {e}")))
+        .collect::<anyhow::Result<Vec<_>>>();
+    if args.validate {
+        let count = targets.and_then(|targets| {
+            let fixed = targets
+                .into_iter()
+                .map(|target| prefixed(prefix, target))
+                .collect::<Vec<_>>();
+            let loaded = args
+                .config
+                .as_deref()
+                .map(|path| load(path, prefix, &fixed))
+                .transpose()?;
+            anyhow::Ok(fixed.len() + loaded.map_or(0, |loaded| loaded.len()))
+        });
+        match count {
+            Ok(count) => {
+                println!("{count} targets are valid");
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{e:#}");
+                std::process::exit(1);
+            }
+        }
+    }
+    let targets = targets?;
+
+    let tls_config = hyper::tls_config(
+        &args.ca_cert,
+        !args.no_default_roots,
+        args.client_cert.as_deref().zip(args.client_key.as_deref()),
+    )?;
+    let resolver = hyper::Resolver::new(args.dns_cache_seconds);
+    let context = probe::Context::new(tls_config.clone(), resolver.clone(), args.connect_order);
+    let (uid, gid) = privilege::resolve(args.user.as_deref(), args.group.as_deref())?;
+
+    if let Some(Command::Bench {
+        config,
+        name,
+        kind,
+        count,
+    }) = &args.command
+    {
+        let mut loaded = Vec::new();
+        for path in [config, &args.config].into_iter().flatten() {
+            loaded.extend(config::load(path)?.into_iter().map(|(target, _)| target));
+        }
+        let Some(target) = targets
+            .iter()
+            .chain(&loaded)
+            .find(|target| target.name == *name)
+        else {
+            anyhow::bail!("no target named {name}");
+        };
+        let mut probes = match kind {
+            Some(kind) => vec![*kind],
+            None => vec![Kind::Liveness, Kind::Readiness, Kind::Startup],
+        }
+        .into_iter()
+        .filter_map(|kind| target.probe(kind));
+        let (Some(probe), None) = (probes.next(), probes.next()) else {
+            anyhow::bail!("{name} has no such probe, or more than one to pick from with --kind");
+        };
+        privilege::drop_to(uid, gid)?;
+        print!("{}", bench(&context, probe, *count).await);
+        return Ok(());
+    }
+
+    let statsd = args.statsd.as_ref().and_then(|addr| {
+        match statsd::Statsd::connect(addr, args.statsd_format) {
+            Ok(statsd) => Some(Arc::new(statsd)),
+            Err(e) => {
+                tracing::warn!(error = e.to_string(), addr, "StatsD disabled");
+                None
+            }
+        }
+    });
+    let prepare = |mut target: Target| {
+        for probe in [
+            &mut target.liveness_probe,
+            &mut target.readiness_probe,
+            &mut target.startup_probe,
+        ]
         .into_iter()
-        .map(|target| (target, Status::default()))
-        .collect();
-
-    futures::future::try_join(
-        serve(args.bind, &targets),
-        futures::future::join_all(
-            targets
-                .iter()
-                .map(|(target, status)| update(&context, target, status)),
+        .flatten()
+        {
+            probe.budget = probe.budget.or(args.probe_timeout_budget_seconds);
+            if let probe::Method::HttpGet {
+                insecure_skip_tls_verify: true,
+                ..
+            } = probe.method
+            {
+                tracing::warn!(
+                    name = target.name,
+                    "TLS certificate verification is DISABLED for this target",
+                );
+            }
+        }
+        let mut status = Status::new(!args.liveness_pessimistic);
+        if let Some(statsd) = &statsd {
+            for (kind, report) in [
+                (Kind::Liveness, &mut status.liveness),
+                (Kind::Readiness, &mut status.readiness),
+                (Kind::Startup, &mut status.startup),
+            ] {
+                report.statsd = Some(statsd.sink(&[
+                    ("target", &target.name),
+                    ("probe", kind.to_possible_value().unwrap().get_name()),
+                ]));
+            }
+        }
+        (target, Arc::new(status))
+    };
+    let fixed = targets
+        .into_iter()
+        .map(|target| prefixed(prefix, target))
+        .collect::<Vec<_>>();
+    let loaded = args
+        .config
+        .as_deref()
+        .map(|path| load(path, prefix, &fixed))
+        .transpose()?
+        .unwrap_or_default();
+    let fixed = fixed.into_iter().map(prepare).collect::<Vec<_>>();
+    let targets = fixed
+        .iter()
+        .cloned()
+        .chain(loaded.iter().map(|(target, _)| prepare(target.clone())))
+        .collect::<Targets>();
+    if args.once {
+        privilege::drop_to(uid, gid)?;
+        std::process::exit(once(&context, &targets).await.into());
+    }
+    if args.check {
+        privilege::drop_to(uid, gid)?;
+        std::process::exit(if check(&context, &targets).await {
+            0
+        } else {
+            1
+        });
+    }
+    let readiness_gate = args
+        .k8s_readiness_gate
+        .map(k8s::ReadinessGate::in_cluster)
+        .transpose()?;
+
+    let watchdog = args
+        .watchdog_interval_seconds
+        .map(|interval| Arc::new(watchdog::Watchdog::new(interval)));
+    if let Some(watchdog) = &watchdog {
+        watchdog.spawn();
+    }
+
+    let draining = Arc::new(AtomicBool::new(false));
+    let reloads = Arc::new(Reloads::default());
+    let shutdown = shutdown(
+        draining.clone(),
+        signaled(args.max_lifetime_seconds)?,
+        args.shutdown_delay_seconds,
+    )
+    .shared();
+
+    let mut runner = Runner {
+        context: Arc::new(context),
+        isolated: args
+            .isolate_clients
+            .then(|| (tls_config.clone(), resolver.clone(), args.connect_order)),
+        tasks: tokio::task::JoinSet::new(),
+        watchdog: watchdog.clone(),
+        loaded: HashMap::new(),
+        reloads: reloads.clone(),
+    };
+    let targets = tokio::sync::watch::Sender::new(runner.start(&fixed, loaded, &prepare));
+    // installed up front, since SIGHUP terminates the process by default
+    let hangup = args
+        .config
+        .as_ref()
+        .map(|_| tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()))
+        .transpose()?;
+
+    let mut app = versioned(
+        router(
+            &targets.subscribe(),
+            &watchdog,
+            &draining,
+            &reloads,
+            args.ready_json,
+            args.liveness_bind.is_none(),
+            args.readiness_bind.is_none(),
+        ),
+        args.app_version.clone(),
+    );
+    if let Some(path) = &args.admin_token_file {
+        let token = std::fs::read_to_string(path)?;
+        let mut admin = admin(&targets.subscribe(), token.trim_end(), started);
+        if !args.admin_allow_cidr.is_empty() {
+            admin = allow_cidr(admin, args.admin_allow_cidr.clone());
+        }
+        app = app.merge(admin);
+    }
+    let layered = |app| layered(app, args.max_connections, args.request_timeout_seconds);
+    let app = layered(app);
+    let live_app = layered(versioned(
+        live_router(&targets.subscribe(), &watchdog),
+        args.app_version.clone(),
+    ));
+    let ready_app = layered(versioned(
+        ready_router(&targets.subscribe(), &draining, args.ready_json),
+        args.app_version.clone(),
+    ));
+
+    // bound while privileged, so that low ports work without running probes as root
+    let listener = match args.bind {
+        Some(addr) => Some(bind(addr, args.bind_retry_seconds).await?),
+        None => None,
+    };
+    let unix_listener = args.unix_socket.as_deref().map(bind_unix).transpose()?;
+    let tls = match (args.tls_bind, &args.tls_cert, &args.tls_key) {
+        (Some(tls_bind), Some(cert), Some(key)) => Some((
+            bind(tls_bind, args.bind_retry_seconds).await?,
+            hyper::server_tls_config(cert, key)?,
+        )),
+        _ => None,
+    };
+    let live_listener = match args.liveness_bind {
+        Some(addr) => Some(bind(addr, args.bind_retry_seconds).await?),
+        None => None,
+    };
+    let ready_listener = match args.readiness_bind {
+        Some(addr) => Some(bind(addr, args.bind_retry_seconds).await?),
+        None => None,
+    };
+    privilege::drop_to(uid, gid)?;
+
+    futures::future::try_join3(
+        futures::future::try_join5(
+            async {
+                if let Some(listener) = listener {
+                    serve(listener, app.clone(), shutdown.clone()).await?;
+                }
+                io::Result::Ok(())
+            },
+            async {
+                if let (Some(listener), Some(path)) = (unix_listener, &args.unix_socket) {
+                    serve_unix(listener, path.clone(), app.clone(), shutdown.clone()).await?;
+                }
+                Ok(())
+            },
+            async {
+                if let Some((listener, tls_config)) = tls {
+                    serve_tls(listener, tls_config, app.clone(), shutdown.clone()).await?;
+                }
+                Ok(())
+            },
+            async {
+                if let Some(listener) = live_listener {
+                    serve(listener, live_app, shutdown.clone()).await?;
+                }
+                Ok(())
+            },
+            async {
+                if let Some(listener) = ready_listener {
+                    serve(listener, ready_app, shutdown.clone()).await?;
+                }
+                Ok(())
+            },
+        ),
+        until(
+            shutdown.clone(),
+            futures::future::join3(
+                async {
+                    if let (Some(path), Some(mut hangup)) = (&args.config, hangup) {
+                        while hangup.recv().await.is_some() {
+                            reload(path, prefix, &mut runner, &fixed, &prepare, &targets);
+                        }
+                    }
+                },
+                async {
+                    if let Some(readiness_gate) = &readiness_gate {
+                        readiness_gate
+                            .run(|| {
+                                !draining.load(Ordering::Relaxed) && all_ready(&targets.borrow())
+                            })
+                            .await;
+                    }
+                },
+                async {
+                    if let Some(path) = &args.state_output {
+                        write_state(path, targets.subscribe()).await;
+                    }
+                },
+            ),
         )
         .map(Ok),
+        until(shutdown.clone(), async {
+            if let Some(watchdog) = &watchdog {
+                watchdog.beat().await;
+            }
+        })
+        .map(Ok),
     )
     .await?;
 
     Ok(())
 }
 
-#[derive(Clone, Deserialize)]
-struct Target {
-    name: String,
-    liveness_probe: Option<probe::Probe>,
-    readiness_probe: Option<probe::Probe>,
-    startup_probe: Option<probe::Probe>,
+fn is_live(target: &Target, status: &Status) -> bool {
+    target
+        .liveness_probe
+        .as_ref()
+        .and_then(|probe| probe.stale(&status.liveness))
+        .unwrap_or_else(|| status.live.load(Ordering::Relaxed))
+        && !waiting_for_startup(target, Kind::Liveness, status)
+}
+
+fn is_ready(target: &Target, status: &Status) -> bool {
+    target
+        .readiness_probe
+        .as_ref()
+        .and_then(|probe| probe.stale(&status.readiness))
+        .unwrap_or_else(|| status.ready.load(Ordering::Relaxed))
+        && !waiting_for_startup(target, Kind::Readiness, status)
+        && target.in_ready_window()
+}
+
+fn waiting_for_startup(target: &Target, kind: Kind, status: &Status) -> bool {
+    target
+        .probe(kind)
+        .is_some_and(|probe| probe.require_startup)
+        && *status.started.borrow() != Startup::Complete
 }
 
 fn parse_target(s: &str) -> Result<Target, String> {
     serde_json::from_str(s).map_err(|e| e.to_string())
 }
 
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse()
+        .map(Duration::from_secs)
+        .map_err(|e| e.to_string())
+}
+
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    match parse_seconds(s)? {
+        Duration::ZERO => Err("must be greater than 0".to_string()),
+        interval => Ok(interval),
+    }
+}
+
+// replaced as a whole when the config file is reloaded
+type Targets = Arc<[(Target, Arc<Status>)]>;
+
 struct Status {
     live: AtomicBool,
     ready: AtomicBool,
+    started: tokio::sync::watch::Sender<Startup>,
+    // when `live` and `ready` last flipped
+    live_transition: Mutex<Option<SystemTime>>,
+    ready_transition: Mutex<Option<SystemTime>>,
+    // notified on every transition
+    changed: tokio::sync::Notify,
+    liveness: probe::Report,
+    readiness: probe::Report,
+    startup: probe::Report,
+}
+
+// stays failed once the startup deadline is exceeded
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Startup {
+    Pending,
+    Complete,
+    Failed,
 }
 
 impl Default for Status {
     fn default() -> Self {
+        Self::new(true)
+    }
+}
+
+impl Status {
+    fn new(live: bool) -> Self {
         Self {
-            live: AtomicBool::new(true),
+            live: AtomicBool::new(live),
             ready: AtomicBool::new(false),
+            started: tokio::sync::watch::Sender::new(Startup::Pending),
+            live_transition: Mutex::new(None),
+            ready_transition: Mutex::new(None),
+            changed: tokio::sync::Notify::new(),
+            liveness: probe::Report::default(),
+            readiness: probe::Report::default(),
+            startup: probe::Report::default(),
+        }
+    }
+
+    fn set_live(&self, value: bool) {
+        if self.live.swap(value, Ordering::Relaxed) != value {
+            *self.live_transition.lock().unwrap() = Some(SystemTime::now());
+            self.changed.notify_one();
         }
     }
+
+    fn set_ready(&self, value: bool) {
+        if self.ready.swap(value, Ordering::Relaxed) != value {
+            *self.ready_transition.lock().unwrap() = Some(SystemTime::now());
+            self.changed.notify_one();
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Readiness<'a> {
+    name: &'a str,
+    ready: bool,
+    duration_seconds: Option<f64>,
+}
+
+fn readiness<T>(targets: &[T]) -> Vec<Readiness<'_>>
+where
+    T: Borrow<(Target, Arc<Status>)>,
+{
+    targets
+        .iter()
+        .map(|t| t.borrow())
+        .map(|(target, status)| Readiness {
+            name: &target.name,
+            ready: is_ready(target, status),
+            duration_seconds: status
+                .readiness
+                .last_duration
+                .lock()
+                .unwrap()
+                .map(|duration| duration.as_secs_f64()),
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct TargetStatus {
+    name: String,
+    liveness: bool,
+    readiness: bool,
+    startup: Startup,
+}
+
+fn status(targets: &[(Target, Arc<Status>)]) -> Vec<TargetStatus> {
+    targets
+        .iter()
+        .map(|(target, status)| TargetStatus {
+            name: target.name.clone(),
+            liveness: is_live(target, status),
+            readiness: is_ready(target, status),
+            startup: *status.started.borrow(),
+        })
+        .collect()
 }
 
-async fn serve(bind: SocketAddr, targets: &Arc<[(Target, Status)]>) -> io::Result<()> {
-    let app = Router::new()
+// https://kubernetes.io/docs/concepts/workloads/pods/pod-lifecycle/#pod-conditions
+#[derive(Serialize)]
+struct Condition {
+    r#type: &'static str,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+fn conditions(targets: &[(Target, Arc<Status>)]) -> Vec<Condition> {
+    let unready = targets
+        .iter()
+        .filter(|(target, status)| !is_ready(target, status))
+        .collect::<Vec<_>>();
+    let ready = if unready.is_empty() {
+        Condition {
+            r#type: "Ready",
+            status: "True",
+            reason: None,
+            message: None,
+        }
+    } else {
+        let names = unready
+            .iter()
+            .map(|(target, _)| target.name.as_str())
+            .collect::<Vec<_>>();
+        let mut message = format!("targets with unready status: [{}]", names.join(" "));
+        for (target, status) in unready {
+            let stale = target
+                .readiness_probe
+                .as_ref()
+                .is_some_and(|probe| probe.stale(&status.readiness).is_some());
+            let error = if !target.in_ready_window() {
+                Some("outside ready window".to_string())
+            } else if waiting_for_startup(target, Kind::Readiness, status) {
+                Some("waiting for startup".to_string())
+            } else if stale {
+                Some("readiness result is stale".to_string())
+            } else {
+                status
+                    .readiness
+                    .last_error
+                    .lock()
+                    .unwrap()
+                    .clone()
+                    .or_else(|| status.startup.last_error.lock().unwrap().clone())
+            };
+            if let Some(error) = error {
+                message.push_str(&format!("; {}: {error}", target.name));
+            }
+        }
+        Condition {
+            r#type: "Ready",
+            status: "False",
+            reason: Some("TargetsNotReady"),
+            message: Some(message),
+        }
+    };
+    vec![ready]
+}
+
+fn live_router(
+    targets: &tokio::sync::watch::Receiver<Targets>,
+    watchdog: &Option<Arc<watchdog::Watchdog>>,
+) -> Router {
+    Router::new().route(
+        "/live",
+        routing::get({
+            let targets = targets.clone();
+            let watchdog = watchdog.clone();
+            async move || {
+                if all_live(&targets.borrow().clone(), watchdog.as_deref()) {
+                    http::StatusCode::OK
+                } else {
+                    http::StatusCode::INTERNAL_SERVER_ERROR
+                }
+            }
+        }),
+    )
+}
+
+fn ready_router(
+    targets: &tokio::sync::watch::Receiver<Targets>,
+    draining: &Arc<AtomicBool>,
+    ready_json: bool,
+) -> Router {
+    Router::new()
         .route(
-            "/live",
+            "/ready",
             routing::get({
                 let targets = targets.clone();
+                let draining = draining.clone();
                 async move || {
-                    if targets
+                    ready(
+                        &targets.borrow().clone(),
+                        draining.load(Ordering::Relaxed),
+                        ready_json,
+                    )
+                }
+            }),
+        )
+        .route(
+            "/ready/group/{group}",
+            routing::get({
+                let targets = targets.clone();
+                let draining = draining.clone();
+                async move |Path(group): Path<String>| {
+                    let targets = targets.borrow().clone();
+                    let targets = targets
                         .iter()
-                        .all(|(_, status)| status.live.load(Ordering::Relaxed))
-                    {
-                        http::StatusCode::OK
+                        .filter(|(target, _)| target.group.as_ref() == Some(&group))
+                        .collect::<Vec<_>>();
+                    if targets.is_empty() {
+                        http::StatusCode::NOT_FOUND.into_response()
                     } else {
-                        http::StatusCode::INTERNAL_SERVER_ERROR
+                        ready(&targets, draining.load(Ordering::Relaxed), ready_json)
                     }
                 }
             }),
         )
+}
+
+fn router(
+    targets: &tokio::sync::watch::Receiver<Targets>,
+    watchdog: &Option<Arc<watchdog::Watchdog>>,
+    draining: &Arc<AtomicBool>,
+    reloads: &Arc<Reloads>,
+    ready_json: bool,
+    with_live: bool,
+    with_ready: bool,
+) -> Router {
+    let mut router = Router::new();
+    if with_live {
+        router = router.merge(live_router(targets, watchdog));
+    }
+    if with_ready {
+        router = router.merge(ready_router(targets, draining, ready_json));
+    }
+    router
         .route(
-            "/ready",
+            "/healthz",
+            routing::get({
+                let targets = targets.clone();
+                let watchdog = watchdog.clone();
+                let draining = draining.clone();
+                async move || {
+                    healthz(
+                        &targets.borrow().clone(),
+                        watchdog.as_deref(),
+                        draining.load(Ordering::Relaxed),
+                    )
+                }
+            }),
+        )
+        .route(
+            "/startupz",
             routing::get({
                 let targets = targets.clone();
                 async move || {
                     if targets
+                        .borrow()
                         .iter()
-                        .all(|(_, status)| status.ready.load(Ordering::Relaxed))
+                        .all(|(_, status)| *status.started.borrow() == Startup::Complete)
                     {
                         http::StatusCode::OK
                     } else {
@@ -111,61 +840,874 @@ async fn serve(bind: SocketAddr, targets: &Arc<[(Target, Status)]>) -> io::Resul
                 }
             }),
         )
-        .layer(tower_http::trace::TraceLayer::new_for_http());
+        .route(
+            "/status",
+            routing::get({
+                let targets = targets.clone();
+                async move || Json(status(&targets.borrow().clone()))
+            }),
+        )
+        .route(
+            "/k8s/conditions",
+            routing::get({
+                let targets = targets.clone();
+                async move || Json(conditions(&targets.borrow().clone()))
+            }),
+        )
+        .route(
+            "/metrics",
+            routing::get({
+                let targets = targets.clone();
+                let reloads = reloads.clone();
+                async move || {
+                    (
+                        [(
+                            http::header::CONTENT_TYPE,
+                            "text/plain; version=0.0.4; charset=utf-8",
+                        )],
+                        metrics(&targets.borrow().clone(), &reloads),
+                    )
+                }
+            }),
+        )
+}
+
+// applied to each listener on its own, so that they don't share the in-flight limit
+fn layered(app: Router, max_connections: Option<usize>, timeout: Option<Duration>) -> Router {
+    app.layer(
+        tower::ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(async |_: tower::BoxError| {
+                http::StatusCode::SERVICE_UNAVAILABLE
+            }))
+            .load_shed()
+            .option_layer(max_connections.map(ConcurrencyLimitLayer::new)),
+    )
+    .layer(tower::util::option_layer(timeout.map(|timeout| {
+        TimeoutLayer::with_status_code(http::StatusCode::REQUEST_TIMEOUT, timeout)
+    })))
+    .layer(tower_http::trace::TraceLayer::new_for_http())
+}
+
+// lets rollout tooling tell which version is responding
+fn versioned(router: Router, app_version: Option<http::HeaderValue>) -> Router {
+    router
+        .layer(SetResponseHeaderLayer::overriding(
+            http::HeaderName::from_static("x-healthzd-version"),
+            http::HeaderValue::from_static(env!("CARGO_PKG_VERSION")),
+        ))
+        .layer(tower::util::option_layer(app_version.map(|app_version| {
+            SetResponseHeaderLayer::overriding(
+                http::HeaderName::from_static("x-app-version"),
+                app_version,
+            )
+        })))
+}
+
+fn all_live(targets: &[(Target, Arc<Status>)], watchdog: Option<&watchdog::Watchdog>) -> bool {
+    watchdog.is_none_or(|watchdog| watchdog.live())
+        && targets
+            .iter()
+            .all(|(target, status)| is_live(target, status))
+}
+
+fn all_ready<T>(targets: &[T]) -> bool
+where
+    T: Borrow<(Target, Arc<Status>)>,
+{
+    targets
+        .iter()
+        .map(|t| t.borrow())
+        .all(|(target, status)| is_ready(target, status))
+}
+
+fn ready<T>(targets: &[T], draining: bool, json: bool) -> axum::response::Response
+where
+    T: Borrow<(Target, Arc<Status>)>,
+{
+    let code = if !draining && all_ready(targets) {
+        http::StatusCode::OK
+    } else {
+        http::StatusCode::SERVICE_UNAVAILABLE
+    };
+    if json {
+        (code, Json(readiness(targets))).into_response()
+    } else {
+        code.into_response()
+    }
+}
+
+// for monitors that only look at a single endpoint
+fn healthz(
+    targets: &[(Target, Arc<Status>)],
+    watchdog: Option<&watchdog::Watchdog>,
+    draining: bool,
+) -> axum::response::Response {
+    let code = if !draining && all_live(targets, watchdog) && all_ready(targets) {
+        http::StatusCode::OK
+    } else {
+        http::StatusCode::SERVICE_UNAVAILABLE
+    };
+    let failing = targets
+        .iter()
+        .filter(|(target, status)| !is_live(target, status) || !is_ready(target, status))
+        .map(|(target, _)| target.name.as_str())
+        .collect::<Vec<_>>();
+    (code, Json(serde_json::json!({ "failing": failing }))).into_response()
+}
+
+// https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+fn metrics(targets: &[(Target, Arc<Status>)], reloads: &Reloads) -> String {
+    let samples = targets
+        .iter()
+        .flat_map(|(target, status)| {
+            [
+                (
+                    Kind::Liveness,
+                    is_live(target, status),
+                    &status.liveness,
+                    Some(&status.live_transition),
+                ),
+                (
+                    Kind::Readiness,
+                    is_ready(target, status),
+                    &status.readiness,
+                    Some(&status.ready_transition),
+                ),
+                (
+                    Kind::Startup,
+                    *status.started.borrow() == Startup::Complete,
+                    &status.startup,
+                    None,
+                ),
+            ]
+            .into_iter()
+            .filter(move |(kind, _, _, _)| target.probe(*kind).is_some())
+            .map(move |(kind, success, report, transition)| {
+                let labels = format!(
+                    r#"name="{}",kind="{}""#,
+                    target
+                        .name
+                        .replace('\\', r"\\")
+                        .replace('"', r#"\""#)
+                        .replace('\n', r"\n"),
+                    kind.to_possible_value().unwrap().get_name(),
+                );
+                (
+                    labels,
+                    [
+                        u64::from(success),
+                        report.checks.load(Ordering::Relaxed),
+                        report.failures.load(Ordering::Relaxed),
+                        report.hangs.load(Ordering::Relaxed),
+                    ],
+                    report,
+                    transition,
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut body = String::new();
+    for (i, (name, r#type, help)) in [
+        (
+            "healthzd_probe_success",
+            "gauge",
+            "Whether the probe is passing.",
+        ),
+        (
+            "healthzd_probe_checks_total",
+            "counter",
+            "Attempts of the probe.",
+        ),
+        (
+            "healthzd_probe_failures_total",
+            "counter",
+            "Failed attempts of the probe.",
+        ),
+        (
+            "healthzd_probe_hangs_total",
+            "counter",
+            "Attempts of the probe that ran into its timeout.",
+        ),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        body.push_str(&format!("# HELP {name} {help}\n# TYPE {name} {type}\n"));
+        for (labels, values, _, _) in &samples {
+            body.push_str(&format!("{name}{{{labels}}} {}\n", values[i]));
+        }
+    }
+
+    let name = "healthzd_probe_last_transition_timestamp_seconds";
+    body.push_str(&format!(
+        "# HELP {name} Unix time the probe last flipped between passing and failing.\n\
+         # TYPE {name} gauge\n"
+    ));
+    for (labels, _, _, transition) in &samples {
+        // startup never flips back, and nothing has flipped yet before the first result
+        if let Some(at) = transition.and_then(timestamp) {
+            body.push_str(&format!("{name}{{{labels}}} {at}\n"));
+        }
+    }
+
+    let name = "healthzd_probe_schedule_lateness_seconds";
+    body.push_str(&format!(
+        "# HELP {name} How long after its scheduled time the last attempt started.\n\
+         # TYPE {name} gauge\n"
+    ));
+    for (labels, _, report, _) in &samples {
+        if let Some(lateness) = *report.schedule_lateness.lock().unwrap() {
+            body.push_str(&format!("{name}{{{labels}}} {}\n", lateness.as_secs_f64()));
+        }
+    }
+
+    let name = "healthzd_probe_duration_seconds";
+    body.push_str(&format!(
+        "# HELP {name} Duration of probe attempts.\n# TYPE {name} histogram\n"
+    ));
+    for (labels, _, report, _) in &samples {
+        let durations = &report.durations;
+        let count = durations.count.load(Ordering::Relaxed);
+        for (bound, bucket) in probe::DURATION_BUCKETS.iter().zip(&durations.buckets) {
+            body.push_str(&format!(
+                "{name}_bucket{{{labels},le=\"{bound}\"}} {}\n",
+                bucket.load(Ordering::Relaxed),
+            ));
+        }
+        body.push_str(&format!("{name}_bucket{{{labels},le=\"+Inf\"}} {count}\n"));
+        body.push_str(&format!(
+            "{name}_sum{{{labels}}} {}\n",
+            durations.sum.lock().unwrap().as_secs_f64(),
+        ));
+        body.push_str(&format!("{name}_count{{{labels}}} {count}\n"));
+    }
+
+    let name = "healthzd_config_reload_total";
+    body.push_str(&format!(
+        "# HELP {name} Reloads of the config file on SIGHUP.\n# TYPE {name} counter\n"
+    ));
+    for (result, count) in [("success", &reloads.success), ("failure", &reloads.failure)] {
+        body.push_str(&format!(
+            "{name}{{result=\"{result}\"}} {}\n",
+            count.load(Ordering::Relaxed),
+        ));
+    }
+    body
+}
+
+fn admin(targets: &tokio::sync::watch::Receiver<Targets>, token: &str, started: Instant) -> Router {
+    let authorization = format!("Bearer {token}");
+    Router::new()
+        .route(
+            "/debug/bundle",
+            routing::get({
+                let targets = targets.clone();
+                async move || Json(bundle(&targets.borrow().clone(), started))
+            }),
+        )
+        .route(
+            "/probe/{name}/status",
+            routing::put({
+                let targets = targets.clone();
+                async move |Path(name): Path<String>, Json(push): Json<Push>| {
+                    push_status(&targets.borrow().clone(), &name, &push)
+                }
+            }),
+        )
+        .route_layer(axum::middleware::from_fn(
+            move |request: axum::extract::Request, next: axum::middleware::Next| {
+                let authorized = request
+                    .headers()
+                    .get(http::header::AUTHORIZATION)
+                    .is_some_and(|value| value == authorization.as_str());
+                async move {
+                    if authorized {
+                        next.run(request).await
+                    } else {
+                        http::StatusCode::UNAUTHORIZED.into_response()
+                    }
+                }
+            },
+        ))
+}
+
+fn allow_cidr(router: Router, allow: Vec<cidr::Cidr>) -> Router {
+    router.route_layer(axum::middleware::from_fn(
+        move |request: axum::extract::Request, next: axum::middleware::Next| {
+            let extensions = request.extensions();
+            // the socket file's permissions already decide who gets in over --unix-socket
+            let allowed =
+                extensions.get::<ConnectInfo<UnixPeer>>().is_some()
+                    || extensions.get::<ConnectInfo<SocketAddr>>().is_some_and(
+                        |ConnectInfo(peer)| allow.iter().any(|cidr| cidr.contains(peer.ip())),
+                    );
+            async move {
+                if allowed {
+                    next.run(request).await
+                } else {
+                    http::StatusCode::FORBIDDEN.into_response()
+                }
+            }
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+struct Push {
+    liveness: Option<bool>,
+    readiness: Option<bool>,
+    startup: Option<bool>,
+}
+
+fn push_status(
+    targets: &[(Target, Arc<Status>)],
+    name: &str,
+    push: &Push,
+) -> Result<http::StatusCode, (http::StatusCode, &'static str)> {
+    let Some((target, _)) = targets.iter().find(|(target, _)| target.name == name) else {
+        return Err((http::StatusCode::NOT_FOUND, "no such target"));
+    };
+    let pushes = [
+        (Kind::Liveness, push.liveness),
+        (Kind::Readiness, push.readiness),
+        (Kind::Startup, push.startup),
+    ]
+    .into_iter()
+    .filter_map(|(kind, healthy)| Some((target.probe(kind), healthy?)))
+    .map(|(probe, healthy)| match probe.map(|probe| &probe.method) {
+        Some(probe::Method::External { pushed, .. }) => Ok((pushed, healthy)),
+        _ => Err((http::StatusCode::BAD_REQUEST, "not an external probe")),
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+    for (pushed, healthy) in pushes {
+        pushed.set(healthy);
+    }
+    Ok(http::StatusCode::NO_CONTENT)
+}
+
+fn timestamp(transition: &Mutex<Option<SystemTime>>) -> Option<f64> {
+    transition
+        .lock()
+        .unwrap()
+        .and_then(|at| at.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|at| at.as_secs_f64())
+}
+
+fn bundle(targets: &[(Target, Arc<Status>)], started: Instant) -> serde_json::Value {
+    fn report(report: &probe::Report) -> serde_json::Value {
+        serde_json::json!({
+            "last_error": *report.last_error.lock().unwrap(),
+            "last_duration_seconds": report
+                .last_duration
+                .lock()
+                .unwrap()
+                .map(|duration| duration.as_secs_f64()),
+            "hangs_total": report.hangs.load(Ordering::Relaxed),
+            "schedule_lateness_seconds": report
+                .schedule_lateness
+                .lock()
+                .unwrap()
+                .map(|lateness| lateness.as_secs_f64()),
+        })
+    }
+
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "uptime_seconds": started.elapsed().as_secs_f64(),
+        "targets": targets
+            .iter()
+            .map(|(target, status)| {
+                serde_json::json!({
+                    "config": target,
+                    "live": status.live.load(Ordering::Relaxed),
+                    "ready": status.ready.load(Ordering::Relaxed),
+                    "live_transition_timestamp_seconds": timestamp(&status.live_transition),
+                    "ready_transition_timestamp_seconds": timestamp(&status.ready_transition),
+                    "liveness": report(&status.liveness),
+                    "readiness": report(&status.readiness),
+                    "startup": report(&status.startup),
+                })
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+async fn write_state(path: &std::path::Path, mut targets: tokio::sync::watch::Receiver<Targets>) {
+    #[derive(Serialize)]
+    struct State<'a> {
+        name: &'a str,
+        live: bool,
+        ready: bool,
+    }
+
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    loop {
+        let current = targets.borrow_and_update().clone();
+        let state = current
+            .iter()
+            .map(|(target, status)| State {
+                name: &target.name,
+                live: status.live.load(Ordering::Relaxed),
+                ready: status.ready.load(Ordering::Relaxed),
+            })
+            .collect::<Vec<_>>();
+        // renamed into place so that readers never see a partial file
+        let output = async {
+            tokio::fs::write(&temp, serde_json::to_vec(&state)?).await?;
+            tokio::fs::rename(&temp, path).await?;
+            anyhow::Ok(())
+        };
+        if let Err(e) = output.await {
+            tracing::warn!(error = e.to_string(), "failed to write state");
+        }
+
+        let changed = async {
+            if current.is_empty() {
+                futures::future::pending().await
+            } else {
+                futures::future::select_all(
+                    current
+                        .iter()
+                        .map(|(_, status)| Box::pin(status.changed.notified())),
+                )
+                .await;
+            }
+        };
+        tokio::select! {
+            _ = changed => {}
+            // the targets were reloaded
+            Ok(()) = targets.changed() => {}
+        }
+    }
+}
+
+// resolves on SIGTERM, SIGINT or once `max_lifetime` has passed
+fn signaled(max_lifetime: Option<Duration>) -> io::Result<impl Future<Output = ()>> {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    let mut interrupt = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())?;
+    Ok(async move {
+        tokio::select! {
+            _ = terminate.recv() => tracing::info!("received SIGTERM"),
+            _ = interrupt.recv() => tracing::info!("received SIGINT"),
+            _ = async {
+                match max_lifetime {
+                    Some(max_lifetime) => tokio::time::sleep(max_lifetime).await,
+                    None => futures::future::pending().await,
+                }
+            } => tracing::info!("reached max lifetime"),
+        }
+    })
+}
+
+// resolves once the process should exit, after reporting unready for `delay`
+async fn shutdown(
+    draining: Arc<AtomicBool>,
+    signaled: impl Future<Output = ()>,
+    delay: Option<Duration>,
+) {
+    signaled.await;
+    draining.store(true, Ordering::Relaxed);
+    if let Some(delay) = delay {
+        tracing::info!(?delay, "draining");
+        tokio::time::sleep(delay).await;
+    }
+}
+
+// runs `future` until `shutdown` resolves
+async fn until(shutdown: impl Future<Output = ()>, future: impl Future) {
+    let mut shutdown = pin::pin!(shutdown);
+    tokio::select! {
+        _ = &mut shutdown => {}
+        _ = future => shutdown.await,
+    }
+}
+
+async fn serve(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown)
+    .await
+}
+
+// attached to requests that came in over --unix-socket, which have no address to check
+#[derive(Clone)]
+struct UnixPeer;
+
+impl
+    axum::extract::connect_info::Connected<
+        axum::serve::IncomingStream<'_, tokio::net::UnixListener>,
+    > for UnixPeer
+{
+    fn connect_info(_: axum::serve::IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        Self
+    }
+}
+
+// removes the socket file once done, so that it does not outlive the process
+async fn serve_unix(
+    listener: tokio::net::UnixListener,
+    path: PathBuf,
+    app: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<UnixPeer>(),
+    )
+    .with_graceful_shutdown(shutdown)
+    .await?;
+    std::fs::remove_file(path)
+}
+
+async fn serve_tls(
+    listener: tokio::net::TcpListener,
+    tls_config: rustls::ServerConfig,
+    app: Router,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(tls_config));
+    let mut shutdown = pin::pin!(shutdown);
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => break Ok(()),
+        };
+        let (stream, peer) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // e.g. running out of file descriptors, which may recover
+                tracing::warn!(error = e.to_string(), "failed to accept");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let service = hyper_util::service::TowerToHyperService::new(
+            app.clone().layer(axum::Extension(ConnectInfo(peer))),
+        );
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!(error = e.to_string(), "TLS handshake failed");
+                    return;
+                }
+            };
+            if let Err(e) =
+                hyper_util::server::conn::auto::Builder::new(hyper_util::rt::TokioExecutor::new())
+                    .serve_connection(hyper_util::rt::TokioIo::new(stream), service)
+                    .await
+            {
+                tracing::debug!(error = e.to_string(), "connection error");
+            }
+        });
+    }
+}
+
+async fn bind(addr: SocketAddr, retry: Option<Duration>) -> io::Result<tokio::net::TcpListener> {
+    let deadline = tokio::time::Instant::now() + retry.unwrap_or_default();
+    let mut backoff = Duration::from_millis(100);
+    loop {
+        match tokio::net::TcpListener::bind(addr).await {
+            Err(e)
+                if e.kind() == io::ErrorKind::AddrInUse
+                    && tokio::time::Instant::now() + backoff < deadline =>
+            {
+                tracing::warn!(error = e.to_string(), ?backoff, "retrying bind");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(5));
+            }
+            output => break output,
+        }
+    }
+}
+
+// a socket file left behind by an unclean exit would fail the bind with AddrInUse
+fn bind_unix(path: &std::path::Path) -> io::Result<tokio::net::UnixListener> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    tokio::net::UnixListener::bind(path)
+}
+
+// resolves once every target in the earlier startup phases has started up
+fn started_before(
+    targets: &[(Target, Arc<Status>)],
+    phase: usize,
+) -> impl Future<Output = ()> + 'static {
+    futures::future::join_all(
+        targets
+            .iter()
+            .filter(move |(target, _)| target.startup_phase < phase)
+            .map(|(_, status)| {
+                let mut started = status.started.subscribe();
+                async move {
+                    let _ = started
+                        .wait_for(|started| *started == Startup::Complete)
+                        .await;
+                }
+            }),
+    )
+    .map(drop)
+}
+
+// runs the probes of each target in a task of its own, so that targets can come and go on reload
+struct Runner {
+    context: Arc<probe::Context>,
+    // gives each target a context of its own when set
+    isolated: Option<(rustls::ClientConfig, hyper::Resolver, hyper::ConnectOrder)>,
+    tasks: tokio::task::JoinSet<()>,
+    // beats from each target task, so that a stuck target fails liveness too
+    watchdog: Option<Arc<watchdog::Watchdog>>,
+    // targets from the config file by name, along with the config they were started from
+    #[allow(clippy::type_complexity)]
+    loaded: HashMap<
+        String,
+        (
+            serde_json::Value,
+            (Target, Arc<Status>),
+            tokio::task::AbortHandle,
+        ),
+    >,
+    reloads: Arc<Reloads>,
+}
+
+#[derive(Default)]
+struct Reloads {
+    success: AtomicU64,
+    failure: AtomicU64,
+}
+
+impl Runner {
+    fn start(
+        &mut self,
+        fixed: &[(Target, Arc<Status>)],
+        loaded: Vec<(Target, serde_json::Value)>,
+        prepare: &impl Fn(Target) -> (Target, Arc<Status>),
+    ) -> Targets {
+        let targets = self.load(fixed, loaded, prepare);
+        for entry in fixed {
+            self.spawn(&targets, entry.clone());
+        }
+        targets
+    }
 
-    let listener = tokio::net::TcpListener::bind(bind).await?;
-    axum::serve(listener, app).await
+    // keeps the targets with unchanged config running along with their status,
+    // and restarts the others from scratch
+    fn load(
+        &mut self,
+        fixed: &[(Target, Arc<Status>)],
+        loaded: Vec<(Target, serde_json::Value)>,
+        prepare: &impl Fn(Target) -> (Target, Arc<Status>),
+    ) -> Targets {
+        while self.tasks.try_join_next().is_some() {}
+        let mut running = std::mem::take(&mut self.loaded);
+        let mut entries = Vec::new();
+        for (target, config) in loaded {
+            let entry = prepare(target);
+            match running.remove(&entry.0.name) {
+                Some((previous, entry, handle)) if previous == config => {
+                    entries.push((config, entry, Some(handle)))
+                }
+                previous => {
+                    if let Some((_, _, handle)) = previous {
+                        handle.abort();
+                    }
+                    entries.push((config, entry, None));
+                }
+            }
+        }
+        for (_, _, handle) in running.into_values() {
+            handle.abort();
+        }
+
+        let targets = fixed
+            .iter()
+            .cloned()
+            .chain(entries.iter().map(|(_, entry, _)| entry.clone()))
+            .collect::<Targets>();
+        for (config, entry, handle) in entries {
+            let handle = handle.unwrap_or_else(|| self.spawn(&targets, entry.clone()));
+            self.loaded
+                .insert(entry.0.name.clone(), (config, entry, handle));
+        }
+        targets
+    }
+
+    fn spawn(
+        &mut self,
+        targets: &[(Target, Arc<Status>)],
+        (target, status): (Target, Arc<Status>),
+    ) -> tokio::task::AbortHandle {
+        let context = match &self.isolated {
+            Some((tls_config, resolver, connect_order)) => Arc::new(probe::Context::new(
+                tls_config.clone(),
+                resolver.clone(),
+                *connect_order,
+            )),
+            None => self.context.clone(),
+        };
+        let barrier = started_before(targets, target.startup_phase);
+        let watchdog = self.watchdog.clone();
+        self.tasks.spawn(async move {
+            let update = update(&context, &target, &status, barrier);
+            match &watchdog {
+                Some(watchdog) => {
+                    futures::future::select(pin::pin!(update), pin::pin!(watchdog.beat())).await;
+                }
+                None => update.await,
+            }
+        })
+    }
+}
+
+fn prefixed(prefix: Option<&str>, mut target: Target) -> Target {
+    if let Some(prefix) = prefix {
+        target.name = format!("{prefix}.{}", target.name);
+    }
+    target
+}
+
+// names are compared once prefixed, and against the --target ones too, which config::load cannot see
+fn load<'a>(
+    path: &std::path::Path,
+    prefix: Option<&str>,
+    fixed: impl IntoIterator<Item = &'a Target>,
+) -> anyhow::Result<Vec<(Target, serde_json::Value)>> {
+    let loaded = config::load(path)?
+        .into_iter()
+        .map(|(target, config)| (prefixed(prefix, target), config))
+        .collect::<Vec<_>>();
+    let mut names = HashSet::new();
+    for name in fixed
+        .into_iter()
+        .map(|target| &target.name)
+        .chain(loaded.iter().map(|(target, _)| &target.name))
+    {
+        if !names.insert(name) {
+            anyhow::bail!("duplicate target {name}");
+        }
+    }
+    Ok(loaded)
+}
+
+// keeps running the previous targets when the config file turns out to be broken
+fn reload(
+    path: &std::path::Path,
+    prefix: Option<&str>,
+    runner: &mut Runner,
+    fixed: &[(Target, Arc<Status>)],
+    prepare: &impl Fn(Target) -> (Target, Arc<Status>),
+    targets: &tokio::sync::watch::Sender<Targets>,
+) {
+    match load(path, prefix, fixed.iter().map(|(target, _)| target)) {
+        Ok(loaded) => {
+            let (mut added, mut changed) = (Vec::new(), Vec::new());
+            for (target, config) in &loaded {
+                match runner.loaded.get(&target.name) {
+                    None => added.push(target.name.clone()),
+                    Some((previous, _, _)) if previous != config => {
+                        changed.push(target.name.clone())
+                    }
+                    Some(_) => {}
+                }
+            }
+            let mut removed = runner
+                .loaded
+                .keys()
+                .filter(|name| !loaded.iter().any(|(target, _)| target.name == **name))
+                .cloned()
+                .collect::<Vec<_>>();
+            removed.sort();
+            targets.send_replace(runner.load(fixed, loaded, prepare));
+            runner.reloads.success.fetch_add(1, Ordering::Relaxed);
+            tracing::info!(path = %path.display(), ?added, ?removed, ?changed, "reloaded");
+        }
+        Err(e) => {
+            runner.reloads.failure.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(error = format!("{e:#}"), "failed to reload");
+        }
+    }
 }
 
 fn update<'a>(
     context: &'a probe::Context,
     target: &'a Target,
     status: &'a Status,
+    barrier: impl Future<Output = ()> + 'a,
 ) -> impl Future<Output = ()> + 'a {
     async move {
-        if let Some(probe) = &target.startup_probe {
-            let mut stream = pin::pin!(
-                probe
-                    .watch(context)
-                    .instrument(tracing::info_span!("startup"))
-            );
-            while let Some(status) = stream.next().await {
-                if status == probe::Status::Success {
-                    break;
+        let startup = async {
+            barrier.await;
+            if let Some(probe) = &target.startup_probe {
+                let mut stream = pin::pin!(
+                    probe
+                        .watch(context, &status.startup)
+                        .instrument(tracing::info_span!("startup"))
+                );
+                while let Some(s) = stream.next().await {
+                    transition(target, Kind::Startup, probe, &status.startup, s);
+                    if s == probe::Status::Success {
+                        break;
+                    }
                 }
             }
+        };
+        if let Some(deadline) = target.startup_deadline_seconds {
+            if tokio::time::timeout(deadline, startup).await.is_err() {
+                tracing::warn!("startup deadline exceeded");
+                status.started.send_replace(Startup::Failed);
+                status.set_live(false);
+                return;
+            }
+        } else {
+            startup.await;
         }
+        status.started.send_replace(Startup::Complete);
         futures::future::join(
             async {
                 if let Some(probe) = &target.liveness_probe {
                     let mut stream = pin::pin!(
                         probe
-                            .watch(context)
+                            .watch(context, &status.liveness)
                             .instrument(tracing::info_span!("liveness"))
                     );
                     while let Some(s) = stream.next().await {
-                        if s == probe::Status::Failure {
-                            status.live.store(false, Ordering::Relaxed);
-                            break;
+                        transition(target, Kind::Liveness, probe, &status.liveness, s);
+                        match s {
+                            probe::Status::Success => status.set_live(true),
+                            probe::Status::Failure => status.set_live(false),
                         }
                     }
+                } else {
+                    status.set_live(true)
                 }
             },
             async {
                 if let Some(probe) = &target.readiness_probe {
                     let mut stream = pin::pin!(
                         probe
-                            .watch(context)
+                            .watch(context, &status.readiness)
                             .instrument(tracing::info_span!("readiness"))
                     );
                     while let Some(s) = stream.next().await {
+                        transition(target, Kind::Readiness, probe, &status.readiness, s);
                         match s {
-                            probe::Status::Success => status.ready.store(true, Ordering::Relaxed),
-                            probe::Status::Failure => status.ready.store(false, Ordering::Relaxed),
+                            probe::Status::Success => status.set_ready(true),
+                            probe::Status::Failure => status.set_ready(false),
                         }
                     }
                 } else {
-                    status.ready.store(true, Ordering::Relaxed)
+                    status.set_ready(true)
                 }
             },
         )
@@ -174,5 +1716,156 @@ fn update<'a>(
     .instrument(tracing::info_span!("target", name = target.name))
 }
 
+fn transition(
+    target: &Target,
+    kind: Kind,
+    probe: &probe::Probe,
+    report: &probe::Report,
+    s: probe::Status,
+) {
+    let (hook, state) = match s {
+        probe::Status::Success => (&probe.on_success, "success"),
+        probe::Status::Failure => (&probe.on_failure, "failure"),
+    };
+    if let Some(statsd) = &report.statsd {
+        statsd.count("probe.transition", &[("state", state)]);
+    }
+    // runs in the background, so a slow hook cannot hold up probing
+    let Some((program, args)) = hook else {
+        return;
+    };
+    let mut command = tokio::process::Command::new(program);
+    command
+        .args(args)
+        .env("HEALTHZD_TARGET", &target.name)
+        .env(
+            "HEALTHZD_PROBE",
+            kind.to_possible_value().unwrap().get_name(),
+        )
+        .env("HEALTHZD_STATE", state);
+    tokio::spawn(
+        async move {
+            match command.status().await {
+                Ok(status) if status.success() => {}
+                Ok(status) => tracing::warn!(%status, "hook failed"),
+                Err(e) => tracing::warn!(error = e.to_string(), "failed to run hook"),
+            }
+        }
+        .in_current_span(),
+    );
+}
+
+// every probe tried once, one after another in the order they would start
+async fn check_all<'a>(
+    context: &probe::Context,
+    targets: &'a [(Target, Arc<Status>)],
+) -> Vec<(&'a Target, Kind, &'a probe::Probe, anyhow::Result<()>)> {
+    let mut results = Vec::new();
+    for (target, _) in targets {
+        for kind in [Kind::Startup, Kind::Liveness, Kind::Readiness] {
+            let Some(probe) = target.probe(kind) else {
+                continue;
+            };
+            let result = probe.try_check(context).await;
+            results.push((target, kind, probe, result));
+        }
+    }
+    results
+}
+
+async fn once(context: &probe::Context, targets: &[(Target, Arc<Status>)]) -> u8 {
+    let mut code = 0;
+    for (target, kind, probe, result) in check_all(context, targets).await {
+        let kind = kind.to_possible_value().unwrap();
+        match result {
+            Ok(_) => println!("{} {}: ok", target.name, kind.get_name()),
+            Err(e) => {
+                println!("{} {}: {e:#}", target.name, kind.get_name());
+                if code == 0 {
+                    code = match probe.method {
+                        probe::Method::HttpGet { .. } => 10,
+                        probe::Method::Exec { .. } => 11,
+                        probe::Method::CertFile { .. } => 12,
+                        probe::Method::NtpOffset { .. } => 13,
+                        probe::Method::External { .. } => 14,
+                        probe::Method::EnvPresent { .. } | probe::Method::SecretPresent { .. } => {
+                            15
+                        }
+                        probe::Method::TcpExchange { .. } => 16,
+                        probe::Method::Redis { .. } => 17,
+                        probe::Method::Postgres { .. } => 18,
+                        probe::Method::FileDescriptors { .. } => 19,
+                        probe::Method::Tcp { .. } => 20,
+                        probe::Method::Grpc { .. } => 21,
+                        probe::Method::Dns { .. } => 22,
+                    };
+                }
+            }
+        }
+    }
+    code
+}
+
+// prints the table once every probe has run, so that it is not interleaved with their logs
+async fn check(context: &probe::Context, targets: &[(Target, Arc<Status>)]) -> bool {
+    let rows = check_all(context, targets)
+        .await
+        .into_iter()
+        .map(|(target, kind, _, result)| {
+            let kind = kind.to_possible_value().unwrap().get_name().to_string();
+            if let Err(e) = &result {
+                tracing::warn!(name = target.name, kind, error = format!("{e:#}"));
+            }
+            (&target.name, kind, result.is_ok())
+        })
+        .collect::<Vec<_>>();
+    let width = rows
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .chain(["TARGET".len()])
+        .max()
+        .unwrap();
+    println!("{:width$}  {:9}  RESULT", "TARGET", "PROBE");
+    for (name, kind, ok) in &rows {
+        let result = if *ok { "PASS" } else { "FAIL" };
+        println!("{name:width$}  {kind:9}  {result}");
+    }
+    rows.iter().all(|(_, _, ok)| *ok)
+}
+
+async fn bench(context: &probe::Context, probe: &probe::Probe, count: usize) -> String {
+    let mut durations = Vec::with_capacity(count);
+    let mut success = 0;
+    for round in 0..count {
+        let start = Instant::now();
+        match probe
+            .call_round(context, round)
+            .instrument(probe.method.span())
+            .await
+        {
+            Ok(_) => success += 1,
+            Err(e) => tracing::warn!(error = format!("{e:#}")),
+        }
+        durations.push(start.elapsed());
+    }
+    summary(durations, success)
+}
+
+// empty without any attempt
+fn summary(mut durations: Vec<Duration>, success: usize) -> String {
+    durations.sort();
+    let (Some(min), Some(max)) = (durations.first(), durations.last()) else {
+        return String::new();
+    };
+    let count = durations.len();
+    let percentile = |p: usize| durations[(count - 1) * p / 100];
+    format!(
+        "count: {count}\nsuccess: {:.1}%\nmin: {min:?}\np50: {:?}\np95: {:?}\nmax: {max:?}\n",
+        success as f64 / count as f64 * 100.,
+        percentile(50),
+        percentile(95),
+    )
+}
+
 #[cfg(test)]
 mod tests;
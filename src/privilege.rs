@@ -0,0 +1,53 @@
+use anyhow::Context;
+use nix::unistd::{Gid, Group, Uid, User};
+
+// accepts names or numeric ids, defaulting the group to the primary group of the user
+pub fn resolve(
+    user: Option<&str>,
+    group: Option<&str>,
+) -> anyhow::Result<(Option<Uid>, Option<Gid>)> {
+    let (uid, primary) = match user {
+        Some(user) => match user.parse() {
+            Ok(uid) => {
+                let uid = Uid::from_raw(uid);
+                (Some(uid), User::from_uid(uid)?.map(|user| user.gid))
+            }
+            Err(_) => {
+                let user =
+                    User::from_name(user)?.with_context(|| format!("no such user {user}"))?;
+                (Some(user.uid), Some(user.gid))
+            }
+        },
+        None => (None, None),
+    };
+    let gid = match group {
+        Some(group) => Some(match group.parse() {
+            Ok(gid) => Gid::from_raw(gid),
+            Err(_) => {
+                Group::from_name(group)?
+                    .with_context(|| format!("no such group {group}"))?
+                    .gid
+            }
+        }),
+        None if uid.is_some() => {
+            Some(primary.context("a group is required for a user without a passwd entry")?)
+        }
+        None => None,
+    };
+    Ok((uid, gid))
+}
+
+// the group goes first, since changing it needs the privileges given up with the user
+pub fn drop_to(uid: Option<Uid>, gid: Option<Gid>) -> anyhow::Result<()> {
+    if let Some(gid) = gid {
+        nix::unistd::setgroups(&[gid]).context("failed to set supplementary groups")?;
+        nix::unistd::setgid(gid).context("failed to set gid")?;
+    }
+    if let Some(uid) = uid {
+        nix::unistd::setuid(uid).context("failed to set uid")?;
+        if !uid.is_root() && nix::unistd::setuid(Uid::from_raw(0)).is_ok() {
+            anyhow::bail!("root privileges could be regained");
+        }
+    }
+    Ok(())
+}
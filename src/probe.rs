@@ -1,35 +1,372 @@
+mod cookie;
 mod de;
+mod grpc;
+mod ntp;
+mod redis;
+mod ser;
+mod template;
 
-use crate::hyper;
+use crate::{hyper, statsd};
+use anyhow::Context as _;
+use base64::Engine as _;
 use bytes::Bytes;
 use futures::{FutureExt, Stream};
+use http_body_util::BodyExt;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt;
-use std::time::Duration;
+use std::net::{IpAddr, Ipv6Addr};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tracing_futures::Instrument;
 
+pub use de::Partial;
+pub use redis::Role;
+pub use template::{Template, Text};
+
 #[derive(Clone, Debug)]
 pub struct Probe {
     pub method: Method,
     pub initial_delay: Duration,
     pub period: Duration,
+    // each period is stretched or shrunk by up to this fraction of itself
+    pub period_jitter: f64,
     pub timeout: Duration,
     pub success_threshold: usize,
     pub failure_threshold: usize,
+    // consecutive timeouts that count as a failure on their own
+    pub hang_threshold: Option<usize>,
+    // failures of this many initial attempts are ignored
+    pub cold_checks: usize,
+    // attempts repeated right away within `timeout` when retryable
+    pub retries: usize,
+    // replaces the consecutive thresholds when set
+    pub window: Option<ResultWindow>,
+    // overrides the result once the last attempt is older than this
+    pub result_ttl: Option<(Duration, StalePolicy)>,
+    // run on transitions with HEALTHZD_TARGET, HEALTHZD_PROBE and HEALTHZD_STATE set
+    pub on_success: Option<(String, Vec<String>)>,
+    pub on_failure: Option<(String, Vec<String>)>,
+    // counts as failing until the startup probe of the target has succeeded
+    pub require_startup: bool,
+    // caps an attempt including its retries, never running into the next period
+    pub budget: Option<Duration>,
+    // multiplies the period after each consecutive failure, up to the duration
+    pub backoff: Option<(f64, Duration)>,
+    pub missed_schedule: MissedSchedule,
+}
+
+// judges by the number of failures and successes among the last `size` results
+#[derive(Clone, Debug)]
+pub struct ResultWindow {
+    pub size: usize,
+    pub failures: usize,
+    pub successes: usize,
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug)]
 pub enum Method {
     Exec {
         command: (String, Vec<String>),
+        nagios: Option<Nagios>,
+        // only failures with matching stderr are retried when set
+        retry_stderr_regex: Option<regex::Regex>,
+        // on top of the environment of healthzd, or of an empty one with `clear_env`
+        env: BTreeMap<String, String>,
+        clear_env: bool,
+        working_dir: Option<PathBuf>,
+        // ignored with `nagios`, which has its own meaning for exit codes
+        success_exit_codes: Vec<i32>,
+        // checked once the exit code is accepted
+        stdout_regex: Option<regex::Regex>,
+        stderr_regex: Option<regex::Regex>,
     },
     HttpGet {
-        uri: http::Uri,
+        // weighted, chosen per attempt
+        uris: Vec<(http::Uri, u32)>,
+        selection: Selection,
+        method: http::Method,
         headers: http::HeaderMap,
+        template: Template,
+        pending_codes: Vec<http::StatusCode>,
+        // any 2xx when unset
+        expected_status: Option<Vec<RangeInclusive<u16>>>,
+        body_regex: Option<regex::Regex>,
+        json_path: Option<(serde_json_path::JsonPath, serde_json::Value)>,
+        redirect: Option<Redirect>,
+        // only these statuses are retried when set
+        retry_on_codes: Option<Vec<http::StatusCode>>,
+        // reads the response from stdin and judges it by its exit status
+        validator_command: Option<(String, Vec<String>)>,
+        // bounds connecting, after which `timeout` bounds the rest of the attempt
+        connect_timeout: Option<Duration>,
+        // accepts any certificate, e.g. self-signed ones
+        insecure_skip_tls_verify: bool,
+        http_version: hyper::HttpVersion,
+    },
+    CertFile {
+        path: PathBuf,
+        min_days: u64,
+    },
+    NtpOffset {
+        server: (String, u16),
+        max_offset: Duration,
+    },
+    EnvPresent {
+        name: String,
+    },
+    SecretPresent {
+        path: PathBuf,
+    },
+    // https://github.com/grpc/grpc/blob/master/doc/health-checking.md
+    Grpc {
+        server: (String, u16),
+        // the overall health of the server when unset
+        service: Option<String>,
+    },
+    // succeeds once a connection is established
+    Tcp {
+        server: (String, u16),
+    },
+    // resolved with the system resolver, bypassing the cache of `Context`
+    Dns {
+        host: String,
+        // at least one of these must be among the addresses when set
+        expect: Option<Vec<IpAddr>>,
+    },
+    TcpExchange {
+        server: (String, u16),
+        send: Vec<u8>,
+        expect: Option<Expect>,
+    },
+    Redis {
+        server: (String, u16),
+        password: Option<template::Text>,
+        db: Option<u32>,
+        expect_role: Option<Role>,
+    },
+    // https://www.postgresql.org/docs/current/libpq-connect.html#LIBPQ-CONNSTRING
+    Postgres {
+        connection_string: template::Text,
+    },
+    FileDescriptors {
+        scope: FdScope,
+        limit: FdLimit,
+    },
+    // healthiness pushed through the admin endpoint, expiring after `ttl`
+    External {
+        ttl: Duration,
+        pushed: Arc<Pushed>,
     },
 }
 
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Selection {
+    #[default]
+    RoundRobin,
+    WeightedRandom,
+}
+
+// what a probe whose results stopped arriving is treated as
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StalePolicy {
+    #[default]
+    FailClosed,
+    FailOpen,
+}
+
+// what follows an attempt that ran past the next scheduled ones
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedSchedule {
+    // waits for the next scheduled time still ahead
+    #[default]
+    Skip,
+    // runs the missed attempts back to back
+    Burst,
+}
+
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FdScope {
+    // this process, against RLIMIT_NOFILE
+    #[default]
+    Process,
+    // the whole node, against fs.file-max
+    System,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum FdLimit {
+    Open(u64),
+    Percent(f64),
+}
+
+#[derive(Clone, Debug)]
+pub enum Expect {
+    Prefix(Vec<u8>),
+    Regex(regex::bytes::Regex),
+}
+
+#[derive(Clone, Debug)]
+pub struct Redirect {
+    // an https -> http redirect is a failure unless this is set
+    pub allow_downgrade: bool,
+    pub max: usize,
+    // carries Set-Cookie values forward to the following requests of the attempt
+    pub cookies: bool,
+}
+
+// schedule lateness beyond this is logged
+const LATENESS_WARNING: Duration = Duration::from_secs(1);
+
+// redirects followed before giving up, unless configured
+pub const MAX_REDIRECTS: usize = 10;
+
+// interprets the exit code and output like a Nagios plugin
+#[derive(Clone, Debug)]
+pub struct Nagios {
+    pub warning_ok: bool,
+}
+
+// https://nagios-plugins.org/doc/guidelines.html#AEN78
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum ServiceState {
+    Ok,
+    Warning,
+    Critical,
+    Unknown,
+}
+
+impl ServiceState {
+    fn from_code(code: Option<i32>) -> Self {
+        match code {
+            Some(0) => Self::Ok,
+            Some(1) => Self::Warning,
+            Some(2) => Self::Critical,
+            _ => Self::Unknown,
+        }
+    }
+
+    fn from_output(line: &str) -> Option<Self> {
+        line.split(|c: char| !c.is_ascii_alphanumeric())
+            .find_map(|word| match word {
+                "OK" => Some(Self::Ok),
+                "WARN" | "WARNING" => Some(Self::Warning),
+                "CRIT" | "CRITICAL" => Some(Self::Critical),
+                "UNKNOWN" => Some(Self::Unknown),
+                _ => None,
+            })
+    }
+}
+
 pub struct Context {
-    pub client: hyper::Client<http_body_util::Empty<Bytes>>,
+    tls_config: rustls::ClientConfig,
+    resolver: hyper::Resolver,
+    connect_order: hyper::ConnectOrder,
+    clients: Mutex<HashMap<ClientOptions, hyper::Client<http_body_util::Full<Bytes>>>>,
+}
+
+// probes with the same options share a client and its connection pool
+#[derive(Clone, Copy, Default, Eq, Hash, PartialEq)]
+struct ClientOptions {
+    connect_timeout: Option<Duration>,
+    version: hyper::HttpVersion,
+    insecure_skip_tls_verify: bool,
+}
+
+impl Context {
+    pub fn new(
+        tls_config: rustls::ClientConfig,
+        resolver: hyper::Resolver,
+        connect_order: hyper::ConnectOrder,
+    ) -> Self {
+        Self {
+            tls_config,
+            resolver,
+            connect_order,
+            clients: Mutex::default(),
+        }
+    }
+
+    fn client(&self, options: ClientOptions) -> hyper::Client<http_body_util::Full<Bytes>> {
+        self.clients
+            .lock()
+            .unwrap()
+            .entry(options)
+            .or_insert_with(|| {
+                hyper::client(
+                    if options.insecure_skip_tls_verify {
+                        hyper::insecure(self.tls_config.clone())
+                    } else {
+                        self.tls_config.clone()
+                    },
+                    self.resolver.clone(),
+                    options.connect_timeout,
+                    self.connect_order,
+                    options.version,
+                )
+            })
+            .clone()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Pushed(Mutex<Option<(bool, tokio::time::Instant)>>);
+
+impl Pushed {
+    pub fn set(&self, healthy: bool) {
+        *self.0.lock().unwrap() = Some((healthy, tokio::time::Instant::now()));
+    }
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub last_error: Mutex<Option<String>>,
+    pub last_duration: Mutex<Option<Duration>>,
+    pub durations: Histogram,
+    pub hangs: AtomicU64,
+    // pending attempts count as neither
+    pub checks: AtomicU64,
+    pub failures: AtomicU64,
+    // how long after its scheduled time the last attempt started
+    pub schedule_lateness: Mutex<Option<Duration>>,
+    // when the last attempt finished
+    pub last_result: Mutex<Option<tokio::time::Instant>>,
+    // when `watch` was called, so that a probe that never finishes an attempt goes stale too
+    pub watching_since: Mutex<Option<tokio::time::Instant>>,
+    pub statsd: Option<statsd::Sink>,
+}
+
+// upper bounds in seconds, as in the default buckets of the Prometheus clients
+pub const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+pub struct Histogram {
+    // cumulative, one per bound in `DURATION_BUCKETS`
+    pub buckets: [AtomicU64; DURATION_BUCKETS.len()],
+    pub count: AtomicU64,
+    pub sum: Mutex<Duration>,
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        for (bound, bucket) in DURATION_BUCKETS.iter().zip(&self.buckets) {
+            if duration.as_secs_f64() <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        *self.sum.lock().unwrap() += duration;
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -38,87 +375,975 @@ pub enum Status {
     Failure,
 }
 
+// counts as neither a success nor a failure
+#[derive(Debug)]
+pub struct Pending {
+    status: http::StatusCode,
+    retry_after: Option<Duration>,
+}
+
+impl fmt::Display for Pending {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{} (pending)", self.status)
+    }
+}
+
+impl std::error::Error for Pending {}
+
+#[derive(Debug)]
+pub struct UnexpectedStatus(http::StatusCode);
+
+impl fmt::Display for UnexpectedStatus {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+impl std::error::Error for UnexpectedStatus {}
+
+#[derive(Debug)]
+pub struct ExecFailed {
+    status: std::process::ExitStatus,
+    // captured only when matched against `retry_stderr_regex`
+    stderr: String,
+}
+
+impl fmt::Display for ExecFailed {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.status.code().is_none() {
+            write!(fmt, "terminated by ")?;
+        }
+        let stderr = self.stderr.trim();
+        if stderr.is_empty() {
+            write!(fmt, "{}", self.status)
+        } else {
+            write!(fmt, "{}: {stderr}", self.status)
+        }
+    }
+}
+
+impl std::error::Error for ExecFailed {}
+
 impl Probe {
-    pub fn watch<'a>(&'a self, context: &'a Context) -> impl Stream<Item = Status> + 'a {
+    // yields the status whenever it changes, starting from the first one determined
+    pub fn watch<'a>(
+        &'a self,
+        context: &'a Context,
+        report: &'a Report,
+    ) -> impl Stream<Item = Status> + 'a {
         struct State {
             deadline: tokio::time::Instant,
             success: usize,
             failure: usize,
+            hang: usize,
+            attempts: usize,
+            results: VecDeque<bool>,
+            rng: rand::rngs::StdRng,
+            // unknown until the first threshold is crossed
+            last: Option<Status>,
+        }
+
+        impl State {
+            fn push(&mut self, window: Option<&ResultWindow>, ok: bool) {
+                if let Some(window) = window {
+                    if self.results.len() == window.size {
+                        self.results.pop_front();
+                    }
+                    self.results.push_back(ok);
+                }
+            }
         }
 
+        *report.watching_since.lock().unwrap() = Some(tokio::time::Instant::now());
+        let mut rng = <rand::rngs::StdRng as rand::SeedableRng>::from_os_rng();
+        // spreads targets restarted together over the first period
+        let offset = self
+            .period
+            .mul_f64(rand::Rng::random_range(&mut rng, 0.0..=self.period_jitter));
         let state = State {
-            deadline: tokio::time::Instant::now() + self.initial_delay,
+            deadline: tokio::time::Instant::now() + self.initial_delay + offset,
             success: 0,
             failure: 0,
+            hang: 0,
+            attempts: 0,
+            results: VecDeque::new(),
+            rng,
+            last: None,
         };
         futures::stream::unfold(state, |mut state| {
             async {
                 loop {
                     tokio::time::sleep_until(state.deadline).await;
-                    state.deadline += self.period;
+                    let start = tokio::time::Instant::now();
+                    let lateness = start.saturating_duration_since(state.deadline);
+                    *report.schedule_lateness.lock().unwrap() = Some(lateness);
+                    if lateness > LATENESS_WARNING {
+                        tracing::warn!(?lateness, "probe started late");
+                    }
+                    state.deadline += jittered(self.period, self.period_jitter, &mut state.rng);
 
-                    match tokio::time::timeout(self.timeout, self.method.call(context))
-                        .map(|output| output?)
-                        .await
-                    {
+                    state.attempts += 1;
+                    let output = self
+                        .attempt(
+                            context,
+                            state.attempts - 1,
+                            Some(state.deadline.saturating_duration_since(start)),
+                        )
+                        .await;
+                    // up to the timeout when the attempt hangs
+                    let duration = start.elapsed();
+                    *report.last_duration.lock().unwrap() = Some(duration);
+                    report.durations.observe(duration);
+                    *report.last_result.lock().unwrap() = Some(tokio::time::Instant::now());
+                    match &output {
+                        Ok(_) => {
+                            report.checks.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(e) if e.is::<Pending>() => {}
+                        Err(_) => {
+                            report.checks.fetch_add(1, Ordering::Relaxed);
+                            report.failures.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    if let Some(statsd) = &report.statsd {
+                        statsd.timing("probe.duration", start.elapsed());
+                        let result = match &output {
+                            Ok(_) => "success",
+                            Err(e) if e.is::<Pending>() => "pending",
+                            Err(e) if e.is::<tokio::time::error::Elapsed>() => "timeout",
+                            Err(_) => "failure",
+                        };
+                        statsd.count("probe.result", &[("result", result)]);
+                    }
+                    match output {
                         Ok(_) => {
                             tracing::info!("ok");
+                            state.push(self.window.as_ref(), true);
                             state.success += 1;
                             state.failure = 0;
+                            state.hang = 0;
+                        }
+                        Err(e) if e.is::<Pending>() => {
+                            tracing::info!(status = format!("{e:#}"));
+                            if let Some(retry_after) = e
+                                .downcast_ref::<Pending>()
+                                .and_then(|pending| pending.retry_after)
+                            {
+                                state.deadline = state
+                                    .deadline
+                                    .min(tokio::time::Instant::now() + retry_after);
+                            }
+                        }
+                        Err(e) if state.attempts <= self.cold_checks => {
+                            tracing::info!(error = format!("{e:#}"), "ignored while cold");
+                            *report.last_error.lock().unwrap() = Some(format!("{e:#}"));
                         }
                         Err(e) => {
-                            tracing::warn!(error = e.to_string());
+                            tracing::warn!(error = format!("{e:#}"));
+                            *report.last_error.lock().unwrap() = Some(format!("{e:#}"));
+                            state.push(self.window.as_ref(), false);
                             state.success = 0;
                             state.failure += 1;
+                            if let Some((multiplier, max)) = self.backoff {
+                                let period = backoff(self.period, multiplier, max, state.failure);
+                                state.deadline =
+                                    start + jittered(period, self.period_jitter, &mut state.rng);
+                            }
+                            if e.is::<tokio::time::error::Elapsed>() {
+                                report.hangs.fetch_add(1, Ordering::Relaxed);
+                                state.hang += 1;
+                            } else {
+                                state.hang = 0;
+                            }
                         }
                     }
 
-                    if state.success == self.success_threshold {
-                        break Some((Status::Success, state));
+                    let now = tokio::time::Instant::now();
+                    if matches!(self.missed_schedule, MissedSchedule::Skip)
+                        && state.deadline < now
+                        && !self.period.is_zero()
+                    {
+                        let missed = (now - state.deadline).div_duration_f64(self.period).ceil();
+                        state.deadline += self.period.mul_f64(missed);
+                        tracing::debug!(missed, "skipped missed schedules");
                     }
-                    if state.failure == self.failure_threshold {
-                        break Some((Status::Failure, state));
+
+                    let status = if let Some(window) = &self.window {
+                        let failures = state.results.iter().filter(|ok| !**ok).count();
+                        if failures >= window.failures || Some(state.hang) == self.hang_threshold {
+                            Some(Status::Failure)
+                        } else if state.results.len() - failures >= window.successes {
+                            Some(Status::Success)
+                        } else {
+                            None
+                        }
+                    } else if state.success == self.success_threshold {
+                        Some(Status::Success)
+                    } else if state.failure == self.failure_threshold
+                        || Some(state.hang) == self.hang_threshold
+                    {
+                        Some(Status::Failure)
+                    } else {
+                        None
+                    };
+                    // repeats are left out, so every item is a transition
+                    if let Some(status) = status
+                        && state.last.replace(status) != Some(status)
+                    {
+                        break Some((status, state));
                     }
                 }
             }
             .instrument(self.method.span())
         })
     }
+
+    // a single attempt, judged on its own without the thresholds of `watch`
+    pub async fn check(&self, context: &Context) -> Status {
+        match self.try_check(context).await {
+            Ok(_) => Status::Success,
+            Err(e) => {
+                tracing::warn!(error = format!("{e:#}"));
+                Status::Failure
+            }
+        }
+    }
+
+    // `check` along with what went wrong
+    pub async fn try_check(&self, context: &Context) -> anyhow::Result<()> {
+        self.attempt(context, 0, None)
+            .instrument(self.method.span())
+            .await
+    }
+
+    // including retries, capped by `budget` and by `next`, the time left until the next attempt
+    async fn attempt(
+        &self,
+        context: &Context,
+        round: usize,
+        next: Option<Duration>,
+    ) -> anyhow::Result<()> {
+        let call = self.call_round(context, round);
+        let Some(budget) = self.budget else {
+            return call.await;
+        };
+        let next = next.unwrap_or(Duration::MAX);
+        tokio::time::timeout(budget.min(next), call)
+            .await
+            .unwrap_or_else(|_| {
+                if budget < next {
+                    Err(anyhow::anyhow!("exceeded the budget of {budget:?}"))
+                } else {
+                    Err(anyhow::anyhow!("ran into the next period"))
+                }
+            })
+    }
+
+    // the result forced by `result_ttl` while the probe has stopped producing results
+    pub fn stale(&self, report: &Report) -> Option<bool> {
+        let (ttl, policy) = self.result_ttl?;
+        let since = match *report.last_result.lock().unwrap() {
+            Some(last_result) => last_result,
+            // from when the first attempt was due
+            None => (*report.watching_since.lock().unwrap())? + self.initial_delay,
+        };
+        (since.elapsed() > ttl).then_some(matches!(policy, StalePolicy::FailOpen))
+    }
+
+    pub async fn call(&self, context: &Context) -> anyhow::Result<()> {
+        self.call_round(context, 0).await
+    }
+
+    // `round` picks the URI of round-robin HTTP probes
+    pub async fn call_round(&self, context: &Context, round: usize) -> anyhow::Result<()> {
+        let retrying = async {
+            let mut retries = 0;
+            loop {
+                match self.method.call(context, round).await {
+                    Err(e) if retries < self.retries && self.method.retryable(&e) => {
+                        tracing::info!(error = format!("{e:#}"), "retrying");
+                        retries += 1;
+                    }
+                    output => break output,
+                }
+            }
+        };
+        let timeout = match &self.method {
+            Method::HttpGet {
+                connect_timeout: Some(connect_timeout),
+                ..
+            } => self.timeout + *connect_timeout,
+            _ => self.timeout,
+        };
+        tokio::time::timeout(timeout, retrying)
+            .map(|output| output?)
+            .await
+    }
+}
+
+// the period after this many consecutive failures
+pub fn backoff(period: Duration, multiplier: f64, max: Duration, failures: usize) -> Duration {
+    let seconds = period.as_secs_f64() * multiplier.powi(failures.try_into().unwrap_or(i32::MAX));
+    // also guards against infinity
+    if seconds < max.as_secs_f64() {
+        Duration::from_secs_f64(seconds)
+    } else {
+        max.max(period)
+    }
+}
+
+// never shrinks the period down to zero, even with a jitter of 1
+pub fn jittered(period: Duration, jitter: f64, rng: &mut impl rand::Rng) -> Duration {
+    if jitter == 0.0 {
+        return period;
+    }
+    period
+        .mul_f64(1.0 + rng.random_range(-jitter..=jitter))
+        .max(Duration::from_millis(1))
+}
+
+// killed once the attempt is given up
+async fn command(
+    program: &str,
+    args: &[String],
+    env: &BTreeMap<String, String>,
+    clear_env: bool,
+    working_dir: Option<&Path>,
+) -> anyhow::Result<tokio::process::Command> {
+    let mut command = tokio::process::Command::new(program);
+    if clear_env {
+        command.env_clear();
+    }
+    if let Some(working_dir) = working_dir {
+        // spawn would report a missing directory as if the program was missing
+        if !tokio::fs::metadata(working_dir)
+            .await
+            .is_ok_and(|metadata| metadata.is_dir())
+        {
+            anyhow::bail!("working directory {} does not exist", working_dir.display());
+        }
+        command.current_dir(working_dir);
+    }
+    command.args(args).envs(env).kill_on_drop(true);
+    Ok(command)
+}
+
+fn check_output(
+    output: &std::process::Output,
+    stdout_regex: Option<&regex::Regex>,
+    stderr_regex: Option<&regex::Regex>,
+) -> anyhow::Result<()> {
+    for (name, regex, data) in [
+        ("stdout", stdout_regex, &output.stdout),
+        ("stderr", stderr_regex, &output.stderr),
+    ] {
+        if let Some(regex) = regex
+            && !regex.is_match(&String::from_utf8_lossy(data))
+        {
+            anyhow::bail!("{name} does not match {regex}");
+        }
+    }
+    Ok(())
+}
+
+// responses are read up to this size
+const BODY_LIMIT: usize = 1 << 20;
+
+async fn collect<B>(body: B) -> anyhow::Result<Bytes>
+where
+    B: http_body::Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    Ok(http_body_util::Limited::new(body, BODY_LIMIT)
+        .collect()
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?
+        .to_bytes())
+}
+
+// the response is written as in HTTP/1.1
+async fn validate(
+    program: &str,
+    args: &[String],
+    parts: &http::response::Parts,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    let mut input = format!("HTTP/1.1 {}\r\n", parts.status).into_bytes();
+    for (name, value) in &parts.headers {
+        input.extend_from_slice(name.as_str().as_bytes());
+        input.extend_from_slice(b": ");
+        input.extend_from_slice(value.as_bytes());
+        input.extend_from_slice(b"\r\n");
+    }
+    input.extend_from_slice(b"\r\n");
+    input.extend_from_slice(body);
+
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    // the validator may exit without reading everything
+    let _ = stdin.write_all(&input).await;
+    drop(stdin);
+    let status = child.wait().await?;
+    if !status.success() {
+        anyhow::bail!("validator {status}");
+    }
+    Ok(())
+}
+
+// https://httpwg.org/specs/rfc9110.html#field.location
+fn resolve(base: &http::Uri, location: &str) -> anyhow::Result<http::Uri> {
+    if location.starts_with("//") {
+        // scheme-relative, taking only the scheme of the base
+        let scheme = base.scheme_str().unwrap_or("http");
+        return Ok(format!("{scheme}:{location}").parse()?);
+    }
+    if location.contains("://") && !location.starts_with(['/', '?']) {
+        return Ok(location.parse()?);
+    }
+    let mut parts = base.clone().into_parts();
+    let path = base.path();
+    parts.path_and_query = Some(if location.starts_with('/') {
+        location.parse()?
+    } else if location.starts_with('?') {
+        // the same path with the new query
+        format!("{path}{location}").parse()?
+    } else {
+        format!("{}{location}", &path[..=path.rfind('/').unwrap_or(0)]).parse()?
+    });
+    Ok(http::Uri::from_parts(parts)?)
 }
 
 impl Method {
-    async fn call(&self, context: &Context) -> anyhow::Result<()> {
+    async fn call(&self, context: &Context, round: usize) -> anyhow::Result<()> {
         match self {
             Self::Exec {
                 command: (program, args),
+                nagios: None,
+                retry_stderr_regex,
+                env,
+                clear_env,
+                working_dir,
+                success_exit_codes,
+                stdout_regex,
+                stderr_regex,
+            } => {
+                let mut command =
+                    command(program, args, env, *clear_env, working_dir.as_deref()).await?;
+                if stdout_regex.is_some() {
+                    command.stdout(std::process::Stdio::piped());
+                }
+                if retry_stderr_regex.is_some() || stderr_regex.is_some() {
+                    command.stderr(std::process::Stdio::piped());
+                }
+                let output = command.spawn()?.wait_with_output().await?;
+                if !output
+                    .status
+                    .code()
+                    .is_some_and(|code| success_exit_codes.contains(&code))
+                {
+                    return Err(ExecFailed {
+                        status: output.status,
+                        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                    }
+                    .into());
+                }
+                check_output(&output, stdout_regex.as_ref(), stderr_regex.as_ref())?;
+            }
+            Self::Exec {
+                command: (program, args),
+                nagios: Some(nagios),
+                env,
+                clear_env,
+                working_dir,
+                stdout_regex,
+                stderr_regex,
+                ..
+            } => {
+                let mut command =
+                    command(program, args, env, *clear_env, working_dir.as_deref()).await?;
+                command.stdout(std::process::Stdio::piped());
+                if stderr_regex.is_some() {
+                    command.stderr(std::process::Stdio::piped());
+                }
+                let output = command.spawn()?.wait_with_output().await?;
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let line = stdout.lines().next().unwrap_or_default().trim();
+                // the worse of the two wins, so scripts that always exit 0 still work
+                let state = ServiceState::from_code(output.status.code())
+                    .max(ServiceState::from_output(line).unwrap_or(ServiceState::Ok));
+                match state {
+                    ServiceState::Ok => {}
+                    ServiceState::Warning if nagios.warning_ok => {
+                        tracing::warn!(output = line, "warning");
+                    }
+                    _ => anyhow::bail!("{state:?}: {line}"),
+                }
+                check_output(&output, stdout_regex.as_ref(), stderr_regex.as_ref())?;
+            }
+            Self::HttpGet {
+                uris,
+                selection,
+                method,
+                headers,
+                template,
+                pending_codes,
+                expected_status,
+                body_regex,
+                json_path,
+                redirect,
+                validator_command,
+                connect_timeout,
+                insecure_skip_tls_verify,
+                http_version,
+                ..
             } => {
-                let status = tokio::process::Command::new(program)
-                    .args(args)
-                    .kill_on_drop(true)
-                    .status()
+                let client = context.client(ClientOptions {
+                    connect_timeout: *connect_timeout,
+                    version: *http_version,
+                    insecure_skip_tls_verify: *insecure_skip_tls_verify,
+                });
+                let index = match selection {
+                    Selection::RoundRobin => round % uris.len(),
+                    Selection::WeightedRandom => {
+                        // summed wider, so that large weights cannot overflow
+                        let total = uris
+                            .iter()
+                            .map(|(_, weight)| u64::from(*weight))
+                            .sum::<u64>();
+                        let mut point = rand::random_range(0..total);
+                        uris.iter()
+                            .position(|(_, weight)| {
+                                let found = point < u64::from(*weight);
+                                point = point.saturating_sub(u64::from(*weight));
+                                found
+                            })
+                            .unwrap()
+                    }
+                };
+                let uri = &uris[index].0;
+                let output = async {
+                    let body = match &template.body {
+                        Some(body) => Bytes::from(body.render().await?),
+                        None => Bytes::new(),
+                    };
+                    let mut rendered = http::HeaderMap::new();
+                    for (name, value) in &template.headers {
+                        let mut value = http::HeaderValue::try_from(value.render().await?)?;
+                        value.set_sensitive(true);
+                        rendered.insert(name, value);
+                    }
+                    if let Some((username, password)) = &template.basic_auth {
+                        let credentials = base64::engine::general_purpose::STANDARD
+                            .encode(format!("{username}:{}", password.render().await?));
+                        let mut value =
+                            http::HeaderValue::try_from(format!("Basic {credentials}"))?;
+                        value.set_sensitive(true);
+                        rendered.insert(http::header::AUTHORIZATION, value);
+                    }
+                    if let Some(path) = &template.bearer_token_file {
+                        let token = tokio::fs::read_to_string(path)
+                            .await
+                            .with_context(|| format!("failed to read {}", path.display()))?;
+                        let token = token.trim_end();
+                        if token.is_empty() {
+                            anyhow::bail!("{} is empty", path.display());
+                        }
+                        let mut value = http::HeaderValue::try_from(format!("Bearer {token}"))?;
+                        value.set_sensitive(true);
+                        rendered.insert(http::header::AUTHORIZATION, value);
+                    }
+                    let mut current = uri.clone();
+                    let mut current_method = method.clone();
+                    // along with the cookies sent there
+                    let mut visited = vec![(uri.clone(), None)];
+                    let mut jar = cookie::Jar::default();
+                    let response = loop {
+                        let mut request =
+                            http::Request::new(http_body_util::Full::new(body.clone()));
+                        request.method_mut().clone_from(&current_method);
+                        request.uri_mut().clone_from(&current);
+                        request.headers_mut().clone_from(headers);
+                        // templated headers may carry credentials for the configured host only
+                        if current.authority() == uri.authority() {
+                            request.headers_mut().extend(rendered.clone());
+                        }
+                        if let Some(cookies) = jar.header(&current) {
+                            let cookies = match request.headers().get(http::header::COOKIE) {
+                                Some(value) => format!("{}; {cookies}", value.to_str()?),
+                                None => cookies,
+                            };
+                            let mut value = http::HeaderValue::try_from(cookies)?;
+                            value.set_sensitive(true);
+                            request.headers_mut().insert(http::header::COOKIE, value);
+                        }
+                        let response = client.request(request).await?;
+                        let Some(redirect) = redirect else {
+                            break response;
+                        };
+                        if redirect.cookies {
+                            jar.store(&current, response.headers());
+                        }
+                        if !response.status().is_redirection()
+                            || response.status() == http::StatusCode::NOT_MODIFIED
+                        {
+                            break response;
+                        }
+                        let Some(location) = response.headers().get(http::header::LOCATION) else {
+                            anyhow::bail!("redirect without a location");
+                        };
+                        if visited.len() > redirect.max {
+                            anyhow::bail!("too many redirects");
+                        }
+                        let next = resolve(&current, location.to_str()?)?;
+                        // revisiting is fine once cookies have changed, e.g. after a login
+                        let visit = (next.clone(), jar.header(&next));
+                        if visited.contains(&visit) {
+                            anyhow::bail!("redirect loop at {next}");
+                        }
+                        visited.push(visit);
+                        if current.scheme() == Some(&http::uri::Scheme::HTTPS)
+                            && next.scheme() != Some(&http::uri::Scheme::HTTPS)
+                            && !redirect.allow_downgrade
+                        {
+                            anyhow::bail!("redirect from {current} downgrades to {next}");
+                        }
+                        tracing::debug!(%next, "redirect");
+                        current = next;
+                        // https://httpwg.org/specs/rfc9110.html#status.303
+                        if response.status() == http::StatusCode::SEE_OTHER
+                            && current_method != http::Method::HEAD
+                        {
+                            current_method = http::Method::GET;
+                        }
+                    };
+                    if pending_codes.contains(&response.status()) {
+                        // https://httpwg.org/specs/rfc9110.html#field.retry-after
+                        let retry_after = response
+                            .headers()
+                            .get(http::header::RETRY_AFTER)
+                            .and_then(|value| value.to_str().ok())
+                            .and_then(|value| {
+                                value.parse().map(Duration::from_secs).ok().or_else(|| {
+                                    httpdate::parse_http_date(value)
+                                        .ok()?
+                                        .duration_since(SystemTime::now())
+                                        .ok()
+                                })
+                            });
+                        return Err(Pending {
+                            status: response.status(),
+                            retry_after,
+                        }
+                        .into());
+                    }
+                    let expected = match expected_status {
+                        Some(expected) => expected
+                            .iter()
+                            .any(|range| range.contains(&response.status().as_u16())),
+                        None => response.status().is_success(),
+                    };
+                    if !expected {
+                        return Err(UnexpectedStatus(response.status()).into());
+                    }
+                    let (parts, body) = response.into_parts();
+                    let body = if body_regex.is_some()
+                        || json_path.is_some()
+                        || validator_command.is_some()
+                    {
+                        collect(body).await?
+                    } else {
+                        Bytes::new()
+                    };
+                    if let Some(regex) = body_regex
+                        && !regex.is_match(&String::from_utf8_lossy(&body))
+                    {
+                        anyhow::bail!("response does not match {regex}");
+                    }
+                    if let Some((path, expected)) = json_path {
+                        let value = serde_json::from_slice::<serde_json::Value>(&body)
+                            .context("response is not JSON")?;
+                        match path.query(&value).exactly_one() {
+                            Ok(value) if value == expected => {}
+                            Ok(value) => anyhow::bail!("{path} is {value}, expected {expected}"),
+                            Err(e) => anyhow::bail!("{path}: {e}"),
+                        }
+                    }
+                    if let Some((program, args)) = validator_command {
+                        validate(program, args, &parts, &body).await?;
+                    }
+                    anyhow::Ok(())
+                };
+                // names the URI that failed when there are several to choose from
+                if uris.len() > 1 {
+                    output
+                        .instrument(tracing::info_span!("uri", index, %uri))
+                        .await
+                        .with_context(|| format!("uris[{index}] {uri}"))?;
+                } else {
+                    output.await?;
+                }
+            }
+            Self::CertFile { path, min_days } => {
+                let data = tokio::fs::read(path).await?;
+                let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+                let mut count = 0;
+                for pem in x509_parser::pem::Pem::iter_from_buffer(&data) {
+                    let pem = pem?;
+                    let certificate = pem.parse_x509()?;
+                    let validity = certificate.validity();
+                    if now.as_secs() < validity.not_before.timestamp() as u64 {
+                        anyhow::bail!(
+                            "{} is not valid before {}",
+                            certificate.subject(),
+                            validity.not_before,
+                        );
+                    }
+                    if now.as_secs() + min_days * 24 * 60 * 60
+                        > validity.not_after.timestamp() as u64
+                    {
+                        anyhow::bail!(
+                            "{} expires at {}",
+                            certificate.subject(),
+                            validity.not_after,
+                        );
+                    }
+                    count += 1;
+                }
+                if count == 0 {
+                    anyhow::bail!("no certificates found");
+                }
+            }
+            Self::NtpOffset {
+                server: (host, port),
+                max_offset,
+            } => {
+                let offset = ntp::offset(host, *port).await?;
+                tracing::debug!(offset);
+                if offset.abs() > max_offset.as_secs_f64() {
+                    anyhow::bail!("clock offset is {offset:.3}s");
+                }
+            }
+            Self::EnvPresent { name } => match std::env::var_os(name) {
+                Some(value) if !value.is_empty() => {}
+                Some(_) => anyhow::bail!("{name} is empty"),
+                None => anyhow::bail!("{name} is not set"),
+            },
+            Self::SecretPresent { path } => {
+                let metadata = tokio::fs::metadata(path).await?;
+                if metadata.len() == 0 {
+                    anyhow::bail!("{} is empty", path.display());
+                }
+            }
+            Self::Grpc {
+                server: (host, port),
+                service,
+            } => {
+                let mut request = http::Request::new(http_body_util::Full::new(grpc::request(
+                    service.as_deref().unwrap_or_default(),
+                )));
+                *request.method_mut() = http::Method::POST;
+                *request.uri_mut() = http::Uri::builder()
+                    .scheme(http::uri::Scheme::HTTP)
+                    .authority(if host.parse::<Ipv6Addr>().is_ok() {
+                        format!("[{host}]:{port}")
+                    } else {
+                        format!("{host}:{port}")
+                    })
+                    .path_and_query(grpc::PATH)
+                    .build()?;
+                request.headers_mut().insert(
+                    http::header::CONTENT_TYPE,
+                    http::HeaderValue::from_static("application/grpc"),
+                );
+                request
+                    .headers_mut()
+                    .insert(http::header::TE, http::HeaderValue::from_static("trailers"));
+                let response = context
+                    .client(ClientOptions {
+                        version: hyper::HttpVersion::Http2,
+                        ..ClientOptions::default()
+                    })
+                    .request(request)
                     .await?;
-                if !status.success() {
-                    anyhow::bail!("{status}");
+                match grpc::response(response).await? {
+                    grpc::ServingStatus::Serving => {}
+                    status => anyhow::bail!("{status:?}"),
+                }
+            }
+            Self::Tcp {
+                server: (host, port),
+            } => {
+                hyper::connect(&context.resolver, host, *port, context.connect_order).await?;
+            }
+            Self::Dns { host, expect } => {
+                let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                    .await?
+                    .map(|addr| addr.ip())
+                    .collect::<Vec<_>>();
+                if addrs.is_empty() {
+                    anyhow::bail!("no addresses for {host}");
+                }
+                if let Some(expect) = expect
+                    && !addrs.iter().any(|addr| expect.contains(addr))
+                {
+                    anyhow::bail!("{host} resolved to {addrs:?}, none of {expect:?}");
+                }
+            }
+            Self::TcpExchange {
+                server: (host, port),
+                send,
+                expect,
+            } => {
+                let mut stream =
+                    hyper::connect(&context.resolver, host, *port, context.connect_order).await?;
+                stream.write_all(send).await?;
+                if let Some(expect) = expect {
+                    let mut buf = Vec::new();
+                    loop {
+                        let matched = match expect {
+                            Expect::Prefix(prefix) => buf.starts_with(prefix),
+                            Expect::Regex(regex) => regex.is_match(&buf),
+                        };
+                        if matched {
+                            break;
+                        }
+                        if buf.len() >= BODY_LIMIT
+                            || matches!(expect, Expect::Prefix(prefix) if buf.len() >= prefix.len())
+                            || stream.read_buf(&mut buf).await? == 0
+                        {
+                            anyhow::bail!(
+                                "unexpected response {:?}",
+                                String::from_utf8_lossy(&buf[..buf.len().min(64)]),
+                            );
+                        }
+                    }
+                }
+            }
+            Self::Redis {
+                server: (host, port),
+                password,
+                db,
+                expect_role,
+            } => {
+                let mut connection = redis::Connection::new(
+                    hyper::connect(&context.resolver, host, *port, context.connect_order).await?,
+                );
+                if let Some(password) = password {
+                    connection.auth(&password.render().await?).await?;
                 }
+                if let Some(db) = db {
+                    connection.select(*db).await?;
+                }
+                // fails with LOADING while the dataset is being loaded
+                connection.ping().await?;
+                if let Some(expect_role) = expect_role {
+                    let role = connection.role().await?;
+                    if role != *expect_role {
+                        anyhow::bail!("role is {role:?}, expected {expect_role:?}");
+                    }
+                }
+            }
+            Self::Postgres { connection_string } => {
+                let config = connection_string
+                    .render()
+                    .await?
+                    .parse::<tokio_postgres::Config>()?;
+                // recorded here since the connection string is only known once rendered
+                let span = tracing::Span::current();
+                if let Some(user) = config.get_user() {
+                    span.record("user", user);
+                }
+                if let Some(dbname) = config.get_dbname() {
+                    span.record("dbname", dbname);
+                }
+                let mut tls_config = context.tls_config.clone();
+                tls_config.alpn_protocols = vec![b"postgresql".to_vec()];
+                let (client, connection) = config
+                    .connect(tokio_postgres_rustls::MakeRustlsConnect::new(tls_config))
+                    .await?;
+                // the connection finishes once the client is dropped
+                futures::future::try_join(connection, async move {
+                    client.simple_query("SELECT 1").await
+                })
+                .await?;
             }
-            Self::HttpGet { uri, headers } => {
-                let mut request = http::Request::new(http_body_util::Empty::new());
-                *request.method_mut() = http::Method::GET;
-                request.uri_mut().clone_from(uri);
-                request.headers_mut().clone_from(headers);
-                let response = context.client.request(request).await?;
-                if !response.status().is_success() {
-                    anyhow::bail!("{}", response.status());
+            Self::FileDescriptors { scope, limit } => {
+                let (open, max) = match scope {
+                    FdScope::Process => {
+                        let mut open = 0;
+                        let mut entries = tokio::fs::read_dir("/proc/self/fd").await?;
+                        while entries.next_entry().await?.is_some() {
+                            open += 1;
+                        }
+                        let (soft, _) = nix::sys::resource::getrlimit(
+                            nix::sys::resource::Resource::RLIMIT_NOFILE,
+                        )?;
+                        (open, soft)
+                    }
+                    // https://docs.kernel.org/admin-guide/sysctl/fs.html#file-max-file-nr
+                    FdScope::System => {
+                        let file_nr = tokio::fs::read_to_string("/proc/sys/fs/file-nr").await?;
+                        let fields = file_nr
+                            .split_whitespace()
+                            .map(str::parse::<u64>)
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let [allocated, unused, max] = fields[..] else {
+                            anyhow::bail!("unexpected file-nr {file_nr:?}");
+                        };
+                        (allocated - unused, max)
+                    }
+                };
+                tracing::debug!(open, max);
+                match limit {
+                    FdLimit::Open(limit) if open > *limit => {
+                        anyhow::bail!("{open} open files exceed {limit}");
+                    }
+                    FdLimit::Percent(limit) if open as f64 > max as f64 * limit / 100. => {
+                        anyhow::bail!("{open} open files exceed {limit}% of {max}");
+                    }
+                    _ => {}
                 }
             }
+            Self::External { ttl, pushed } => match *pushed.0.lock().unwrap() {
+                Some((healthy, at)) if at.elapsed() <= *ttl => {
+                    if !healthy {
+                        anyhow::bail!("pushed unhealthy");
+                    }
+                }
+                Some(_) => anyhow::bail!("pushed status expired"),
+                None => anyhow::bail!("no status pushed"),
+            },
         }
         Ok(())
     }
 
-    fn span(&self) -> tracing::Span {
+    fn retryable(&self, e: &anyhow::Error) -> bool {
+        match self {
+            Self::HttpGet {
+                retry_on_codes: Some(codes),
+                ..
+            } => e
+                .downcast_ref::<UnexpectedStatus>()
+                .is_some_and(|status| codes.contains(&status.0)),
+            Self::Exec {
+                retry_stderr_regex: Some(regex),
+                ..
+            } => e
+                .downcast_ref::<ExecFailed>()
+                .is_some_and(|failed| regex.is_match(&failed.stderr)),
+            _ => !e.is::<Pending>(),
+        }
+    }
+
+    pub fn span(&self) -> tracing::Span {
         match self {
             Self::Exec {
                 command: (program, args),
+                ..
             } => {
                 struct Command<'a> {
                     program: &'a String,
@@ -137,9 +1362,48 @@ impl Method {
                 let command = Command { program, args };
                 tracing::info_span!("exec", ?command)
             }
-            Self::HttpGet { uri, .. } => {
-                tracing::info_span!("http_get", ?uri)
+            Self::HttpGet { uris, .. } => {
+                let uri = uris
+                    .iter()
+                    .map(|(uri, _)| uri.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                tracing::info_span!("http_get", uri)
+            }
+            Self::CertFile { path, .. } => {
+                tracing::info_span!("cert_file", ?path)
+            }
+            Self::NtpOffset {
+                server: (host, port),
+                ..
+            } => {
+                tracing::info_span!("ntp", host, port)
             }
+            Self::EnvPresent { name } => tracing::info_span!("env_present", var = name),
+            Self::SecretPresent { path } => tracing::info_span!("secret_present", ?path),
+            Self::Grpc {
+                server: (host, port),
+                service,
+            } => tracing::info_span!("grpc", host, port, service = service.as_deref()),
+            Self::Tcp {
+                server: (host, port),
+            } => tracing::info_span!("tcp", host, port),
+            Self::Dns { host, .. } => tracing::info_span!("dns", host),
+            Self::TcpExchange {
+                server: (host, port),
+                ..
+            } => tracing::info_span!("tcp_exchange", host, port),
+            Self::Redis {
+                server: (host, port),
+                ..
+            } => tracing::info_span!("redis", host, port),
+            Self::FileDescriptors { scope, .. } => tracing::info_span!("fd", ?scope),
+            Self::Postgres { .. } => tracing::info_span!(
+                "postgres",
+                user = tracing::field::Empty,
+                dbname = tracing::field::Empty,
+            ),
+            Self::External { .. } => tracing::info_span!("external"),
         }
     }
 }
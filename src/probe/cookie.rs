@@ -0,0 +1,104 @@
+use std::time::SystemTime;
+
+// cookies set during a single attempt, carried across its redirects
+// https://httpwg.org/specs/rfc6265.html
+#[derive(Default)]
+pub struct Jar(Vec<Cookie>);
+
+struct Cookie {
+    name: String,
+    value: String,
+    // without a Domain attribute, the cookie is sent back to the same host only
+    domain: (String, bool),
+    path: String,
+    secure: bool,
+}
+
+impl Jar {
+    pub fn store(&mut self, uri: &http::Uri, headers: &http::HeaderMap) {
+        let host = uri.host().unwrap_or_default().to_ascii_lowercase();
+        for value in headers.get_all(http::header::SET_COOKIE) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            let mut attributes = value.split(';').map(str::trim);
+            let Some((name, value)) = attributes.next().and_then(|pair| pair.split_once('='))
+            else {
+                continue;
+            };
+            let mut cookie = Cookie {
+                name: name.trim().to_string(),
+                value: value.trim().to_string(),
+                domain: (host.clone(), true),
+                path: default_path(uri.path()).to_string(),
+                secure: false,
+            };
+            let mut expired = false;
+            for attribute in attributes {
+                let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+                match &*key.trim().to_ascii_lowercase() {
+                    "domain" => {
+                        let domain = value.trim().trim_start_matches('.').to_ascii_lowercase();
+                        // a host may not set cookies for unrelated domains
+                        if !domain_match(&host, &domain) {
+                            expired = true;
+                        }
+                        cookie.domain = (domain, false);
+                    }
+                    "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                    "secure" => cookie.secure = true,
+                    "max-age" => expired |= value.parse::<i64>().is_ok_and(|age| age <= 0),
+                    "expires" => {
+                        expired |= httpdate::parse_http_date(value)
+                            .is_ok_and(|expires| expires <= SystemTime::now())
+                    }
+                    _ => {}
+                }
+            }
+            self.0.retain(|stored| {
+                (&stored.name, &stored.domain.0, &stored.path)
+                    != (&cookie.name, &cookie.domain.0, &cookie.path)
+            });
+            if !expired {
+                self.0.push(cookie);
+            }
+        }
+    }
+
+    // the value of the Cookie header for a request to `uri`
+    pub fn header(&self, uri: &http::Uri) -> Option<String> {
+        let host = uri.host().unwrap_or_default().to_ascii_lowercase();
+        let https = uri.scheme() == Some(&http::uri::Scheme::HTTPS);
+        let cookies = self
+            .0
+            .iter()
+            .filter(|cookie| match &cookie.domain {
+                (domain, true) => *domain == host,
+                (domain, false) => domain_match(&host, domain),
+            })
+            .filter(|cookie| path_match(uri.path(), &cookie.path))
+            .filter(|cookie| https || !cookie.secure)
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>();
+        (!cookies.is_empty()).then(|| cookies.join("; "))
+    }
+}
+
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+fn path_match(path: &str, cookie: &str) -> bool {
+    path.strip_prefix(cookie)
+        .is_some_and(|rest| cookie.ends_with('/') || rest.is_empty() || rest.starts_with('/'))
+}
+
+fn default_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
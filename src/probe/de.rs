@@ -1,63 +1,356 @@
 use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
 use std::fmt::Write;
+use std::net::Ipv6Addr;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
 use std::time::Duration;
 
-impl<'de> Deserialize<'de> for super::Probe {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+// a probe whose method may be omitted and inherited from its target
+#[serde_with::serde_as]
+#[derive(Deserialize)]
+pub struct Partial {
+    #[serde(flatten)]
+    method: serde_json::Map<String, serde_json::Value>,
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    initial_delay_seconds: Option<Duration>,
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    period_seconds: Option<Duration>,
+    period_jitter: Option<f64>,
+    #[serde(rename = "timeout_seconds")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    timeout_seconds: Option<Duration>,
+    success_threshold: Option<usize>,
+    failure_threshold: Option<usize>,
+    hang_threshold: Option<usize>,
+    cold_checks: Option<usize>,
+    retries: Option<usize>,
+    window: Option<usize>,
+    fail_in_window: Option<usize>,
+    success_in_window: Option<usize>,
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    result_ttl_seconds: Option<Duration>,
+    result_ttl_policy: Option<super::StalePolicy>,
+    on_success: Option<Vec<String>>,
+    on_failure: Option<Vec<String>>,
+    require_startup: Option<bool>,
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    budget_seconds: Option<Duration>,
+    backoff_multiplier: Option<f64>,
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    max_backoff_seconds: Option<Duration>,
+    missed_schedule: Option<super::MissedSchedule>,
+}
+
+impl Partial {
+    pub fn complete<E>(self, method: Option<&super::Method>) -> Result<super::Probe, E>
     where
-        D: Deserializer<'de>,
+        E: serde::de::Error,
     {
-        #[serde_with::serde_as]
-        #[derive(Deserialize)]
-        struct Probe {
-            #[serde(flatten)]
-            method: super::Method,
-            #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
-            initial_delay_seconds: Option<Duration>,
-            #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
-            period_seconds: Option<Duration>,
-            #[serde(rename = "timeout_seconds")]
-            #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
-            timeout_seconds: Option<Duration>,
-            success_threshold: Option<usize>,
-            failure_threshold: Option<usize>,
+        let method = if self.method.is_empty() {
+            method
+                .cloned()
+                .ok_or_else(|| E::custom("missing probe method"))?
+        } else {
+            super::Method::deserialize(serde_json::Value::Object(self.method)).map_err(E::custom)?
+        };
+        if self
+            .period_jitter
+            .is_some_and(|jitter| !(0.0..=1.0).contains(&jitter))
+        {
+            return Err(E::custom("period_jitter must be between 0.0 and 1.0"));
+        }
+        for (name, threshold) in [
+            ("success_threshold", self.success_threshold),
+            ("failure_threshold", self.failure_threshold),
+            ("hang_threshold", self.hang_threshold),
+        ] {
+            if threshold == Some(0) {
+                return Err(E::invalid_value(
+                    serde::de::Unexpected::Unsigned(0),
+                    &format!("a positive {name}").as_str(),
+                ));
+            }
+        }
+        let window = match (self.window, self.fail_in_window, self.success_in_window) {
+            (Some(size), Some(failures), Some(successes)) => {
+                if failures == 0 || successes == 0 || failures > size || successes > size {
+                    return Err(E::custom(
+                        "fail_in_window and success_in_window must be between 1 and window",
+                    ));
+                }
+                // otherwise a window could be both healthy and unhealthy
+                if failures + successes <= size {
+                    return Err(E::custom(
+                        "fail_in_window + success_in_window must exceed window",
+                    ));
+                }
+                Some(super::ResultWindow {
+                    size,
+                    failures,
+                    successes,
+                })
+            }
+            (None, None, None) => None,
+            _ => {
+                return Err(E::custom(
+                    "window, fail_in_window and success_in_window must be given together",
+                ));
+            }
+        };
+        let result_ttl = match (self.result_ttl_seconds, self.result_ttl_policy) {
+            (Some(ttl), policy) => Some((ttl, policy.unwrap_or_default())),
+            (None, None) => None,
+            (None, Some(_)) => return Err(E::missing_field("result_ttl_seconds")),
+        };
+        let backoff = match (self.backoff_multiplier, self.max_backoff_seconds) {
+            (Some(multiplier), Some(max)) if multiplier >= 1.0 => Some((multiplier, max)),
+            (Some(_), Some(_)) => {
+                return Err(E::custom("backoff_multiplier must be at least 1.0"));
+            }
+            (None, None) => None,
+            _ => {
+                return Err(E::custom(
+                    "backoff_multiplier and max_backoff_seconds must be given together",
+                ));
+            }
+        };
+        fn command<E>(command: Option<Vec<String>>) -> Result<Option<(String, Vec<String>)>, E>
+        where
+            E: serde::de::Error,
+        {
+            match command {
+                Some(mut command) if !command.is_empty() => Ok(Some((command.remove(0), command))),
+                Some(command) => Err(E::invalid_length(command.len(), &"one or more")),
+                None => Ok(None),
+            }
         }
 
-        let value = Probe::deserialize(deserializer)?;
         // https://kubernetes.io/docs/tasks/configure-pod-container/configure-liveness-readiness-startup-probes/#configure-probes
-        Ok(Self {
-            method: value.method,
-            initial_delay: value
-                .initial_delay_seconds
-                .unwrap_or(Duration::from_secs(0)),
-            period: value.period_seconds.unwrap_or(Duration::from_secs(10)),
-            timeout: value.timeout_seconds.unwrap_or(Duration::from_secs(1)),
-            success_threshold: value.success_threshold.unwrap_or(1),
-            failure_threshold: value.failure_threshold.unwrap_or(3),
+        Ok(super::Probe {
+            method,
+            initial_delay: self.initial_delay_seconds.unwrap_or(Duration::from_secs(0)),
+            period: self.period_seconds.unwrap_or(Duration::from_secs(10)),
+            period_jitter: self.period_jitter.unwrap_or(0.0),
+            timeout: self.timeout_seconds.unwrap_or(Duration::from_secs(1)),
+            success_threshold: self.success_threshold.unwrap_or(1),
+            failure_threshold: self.failure_threshold.unwrap_or(3),
+            hang_threshold: self.hang_threshold,
+            cold_checks: self.cold_checks.unwrap_or(0),
+            retries: self.retries.unwrap_or(0),
+            window,
+            result_ttl,
+            on_success: command(self.on_success)?,
+            on_failure: command(self.on_failure)?,
+            require_startup: self.require_startup.unwrap_or(false),
+            budget: self.budget_seconds,
+            backoff,
+            missed_schedule: self.missed_schedule.unwrap_or_default(),
         })
     }
 }
 
+impl<'de> Deserialize<'de> for super::Probe {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Partial::deserialize(deserializer)?.complete(None)
+    }
+}
+
 impl<'de> Deserialize<'de> for super::Method {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
+        #[allow(clippy::large_enum_variant)]
+        #[serde_with::serde_as]
         #[derive(Deserialize)]
         #[serde(rename_all = "snake_case")]
         enum Method {
             Exec {
                 command: Vec<String>,
+                nagios: Option<Nagios>,
+                retry_stderr_regex: Option<String>,
+                #[serde(default)]
+                env: BTreeMap<String, String>,
+                #[serde(default)]
+                clear_env: bool,
+                working_dir: Option<PathBuf>,
+                success_exit_codes: Option<Vec<i32>>,
+                stdout_regex: Option<String>,
+                stderr_regex: Option<String>,
             },
             // https://kubernetes.io/docs/tasks/configure-pod-container/configure-liveness-readiness-startup-probes/#http-probes
             HttpGet {
                 host: Option<String>,
                 scheme: Option<Scheme>,
                 path: Option<String>,
+                #[serde(with = "http_serde::option::method", default)]
+                http_method: Option<http::Method>,
                 #[serde(with = "http_serde::option::header_map", default)]
                 http_headers: Option<http::HeaderMap>,
                 port: Option<u16>,
+                // sent as is, implying POST unless http_method says otherwise
+                body: Option<String>,
+                content_type: Option<String>,
+                basic_auth: Option<BasicAuth>,
+                bearer_token_file: Option<PathBuf>,
+                template: Option<super::Template>,
+                pending_codes: Option<Vec<u16>>,
+                expected_status: Option<ExpectedStatus>,
+                body_regex: Option<String>,
+                json_path: Option<serde_json_path::JsonPath>,
+                json_value: Option<serde_json::Value>,
+                #[serde(default)]
+                follow_redirects: bool,
+                #[serde(default)]
+                allow_downgrade_redirect: bool,
+                max_redirects: Option<usize>,
+                #[serde(default)]
+                redirect_cookies: bool,
+                retry_on_codes: Option<Vec<u16>>,
+                validator_command: Option<Vec<String>>,
+                #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+                connect_timeout_seconds: Option<Duration>,
+                #[serde(default)]
+                insecure_skip_tls_verify: bool,
+                #[serde(default)]
+                http_version: super::hyper::HttpVersion,
+                // instead of host, scheme, path and port
+                uris: Option<Vec<WeightedUri>>,
+                #[serde(default)]
+                selection: super::Selection,
             },
+            CertFile {
+                path: PathBuf,
+                min_days: Option<u64>,
+            },
+            #[serde(rename = "ntp")]
+            NtpOffset {
+                server: String,
+                port: Option<u16>,
+                #[serde_as(as = "serde_with::DurationSecondsWithFrac<f64>")]
+                max_offset_seconds: Duration,
+            },
+            EnvPresent {
+                name: String,
+            },
+            SecretPresent {
+                path: PathBuf,
+            },
+            // https://kubernetes.io/docs/tasks/configure-pod-container/configure-liveness-readiness-startup-probes/#grpc-probes
+            Grpc {
+                host: Option<String>,
+                port: u16,
+                service: Option<String>,
+            },
+            #[serde(rename = "tcp_socket")]
+            Tcp {
+                host: Option<String>,
+                port: u16,
+            },
+            Dns {
+                host: String,
+                expect: Option<Vec<std::net::IpAddr>>,
+            },
+            TcpExchange {
+                host: Option<String>,
+                port: u16,
+                send: Option<String>,
+                send_hex: Option<String>,
+                expect_prefix: Option<String>,
+                expect_regex: Option<String>,
+            },
+            Redis {
+                host: Option<String>,
+                port: Option<u16>,
+                password: Option<super::template::Text>,
+                db: Option<u32>,
+                expect_role: Option<super::Role>,
+            },
+            Postgres {
+                connection_string: super::template::Text,
+            },
+            #[serde(rename = "fd")]
+            FileDescriptors {
+                #[serde(default)]
+                scope: super::FdScope,
+                max_open: Option<u64>,
+                max_percent: Option<f64>,
+            },
+            External {
+                #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+                ttl_seconds: Duration,
+            },
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum WeightedUri {
+            Uri(#[serde(with = "http_serde::uri")] http::Uri),
+            Weighted {
+                #[serde(with = "http_serde::uri")]
+                uri: http::Uri,
+                weight: u32,
+            },
+        }
+
+        // e.g. 418, "200-299" or a list of them
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum ExpectedStatus {
+            One(StatusRange),
+            Many(Vec<StatusRange>),
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum StatusRange {
+            Code(u16),
+            Range(String),
+        }
+
+        impl StatusRange {
+            fn parse<E>(self) -> Result<RangeInclusive<u16>, E>
+            where
+                E: serde::de::Error,
+            {
+                let invalid = |s: &str| {
+                    E::invalid_value(
+                        serde::de::Unexpected::Str(s),
+                        &"a status code or a range such as 200-299",
+                    )
+                };
+                let range = match self {
+                    Self::Code(code) => code..=code,
+                    Self::Range(s) => {
+                        let (start, end) = s.split_once('-').unwrap_or((&s, &s));
+                        match (start.trim().parse(), end.trim().parse()) {
+                            (Ok(start), Ok(end)) if start <= end => start..=end,
+                            _ => return Err(invalid(&s)),
+                        }
+                    }
+                };
+                for code in [range.start(), range.end()] {
+                    http::StatusCode::from_u16(*code).map_err(E::custom)?;
+                }
+                Ok(range)
+            }
+        }
+
+        // the password may use placeholders, like template values
+        #[derive(Deserialize)]
+        struct BasicAuth {
+            username: String,
+            password: super::template::Text,
+        }
+
+        #[derive(Deserialize)]
+        struct Nagios {
+            #[serde(default)]
+            warning_ok: bool,
         }
 
         #[derive(Deserialize)]
@@ -67,17 +360,95 @@ impl<'de> Deserialize<'de> for super::Method {
             Https,
         }
 
+        fn http_uri<E>(
+            host: Option<String>,
+            scheme: Option<Scheme>,
+            path: Option<String>,
+            port: Option<u16>,
+        ) -> Result<http::Uri, E>
+        where
+            E: serde::de::Error,
+        {
+            let mut uri = String::new();
+            match scheme {
+                Some(Scheme::Http) | None => uri.push_str("http"),
+                Some(Scheme::Https) => uri.push_str("https"),
+            }
+            uri.push_str("://");
+            if let Some(host) = host {
+                // an IPv6 literal is bracketed apart from the port
+                if host.parse::<Ipv6Addr>().is_ok() {
+                    write!(&mut uri, "[{host}]").unwrap();
+                } else {
+                    uri.push_str(&host);
+                }
+            } else {
+                uri.push_str("localhost");
+            }
+            if let Some(port) = port {
+                write!(&mut uri, ":{port}").unwrap();
+            }
+            if let Some(path) = path {
+                uri.push_str(&path);
+            } else {
+                uri.push('/');
+            }
+            uri.parse().map_err(E::custom)
+        }
+
         let value = Method::deserialize(deserializer)?;
         match value {
-            Method::Exec { mut command } => {
+            Method::Exec {
+                mut command,
+                nagios,
+                retry_stderr_regex,
+                env,
+                clear_env,
+                working_dir,
+                success_exit_codes,
+                stdout_regex,
+                stderr_regex,
+            } => {
                 if command.is_empty() {
                     Err(serde::de::Error::invalid_length(
                         command.len(),
                         &"one or more",
                     ))
+                } else if nagios.is_some() && success_exit_codes.is_some() {
+                    Err(serde::de::Error::custom(
+                        "success_exit_codes cannot be used with nagios",
+                    ))
+                } else if nagios.is_some() && retry_stderr_regex.is_some() {
+                    Err(serde::de::Error::custom(
+                        "retry_stderr_regex cannot be used with nagios",
+                    ))
+                } else if success_exit_codes.as_ref().is_some_and(Vec::is_empty) {
+                    Err(serde::de::Error::invalid_length(0, &"one or more"))
                 } else {
                     Ok(Self::Exec {
                         command: (command.remove(0), command),
+                        nagios: nagios.map(|nagios| super::Nagios {
+                            warning_ok: nagios.warning_ok,
+                        }),
+                        retry_stderr_regex: retry_stderr_regex
+                            .as_deref()
+                            .map(regex::Regex::new)
+                            .transpose()
+                            .map_err(serde::de::Error::custom)?,
+                        env,
+                        clear_env,
+                        working_dir,
+                        success_exit_codes: success_exit_codes.unwrap_or_else(|| vec![0]),
+                        stdout_regex: stdout_regex
+                            .as_deref()
+                            .map(regex::Regex::new)
+                            .transpose()
+                            .map_err(serde::de::Error::custom)?,
+                        stderr_regex: stderr_regex
+                            .as_deref()
+                            .map(regex::Regex::new)
+                            .transpose()
+                            .map_err(serde::de::Error::custom)?,
                     })
                 }
             }
@@ -85,33 +456,313 @@ impl<'de> Deserialize<'de> for super::Method {
                 host,
                 scheme,
                 path,
+                http_method,
                 http_headers,
                 port,
+                body,
+                content_type,
+                basic_auth,
+                bearer_token_file,
+                template,
+                pending_codes,
+                expected_status,
+                body_regex,
+                json_path,
+                json_value,
+                follow_redirects,
+                allow_downgrade_redirect,
+                max_redirects,
+                redirect_cookies,
+                retry_on_codes,
+                validator_command,
+                connect_timeout_seconds,
+                insecure_skip_tls_verify,
+                http_version,
+                uris,
+                selection,
             } => {
-                let mut uri = String::new();
-                match scheme {
-                    Some(Scheme::Http) | None => uri.push_str("http"),
-                    Some(Scheme::Https) => uri.push_str("https"),
+                if !follow_redirects && (max_redirects.is_some() || redirect_cookies) {
+                    return Err(serde::de::Error::custom(
+                        "max_redirects and redirect_cookies require follow_redirects",
+                    ));
                 }
-                uri.push_str("://");
-                if let Some(host) = host {
-                    uri.push_str(&host);
-                } else {
-                    uri.push_str("localhost");
+                let mut template = template.unwrap_or_default();
+                if let Some(body) = &body {
+                    if template.body.is_some() {
+                        return Err(serde::de::Error::custom(
+                            "body cannot be combined with template.body",
+                        ));
+                    }
+                    template.body = Some(super::template::Text::literal(body.clone()));
                 }
-                if let Some(port) = port {
-                    write!(&mut uri, ":{port}").unwrap();
+                let mut http_headers = http_headers.unwrap_or_default();
+                if let Some(content_type) = content_type {
+                    if http_headers.contains_key(http::header::CONTENT_TYPE) {
+                        return Err(serde::de::Error::custom(
+                            "content_type cannot be combined with a content-type header",
+                        ));
+                    }
+                    http_headers.insert(
+                        http::header::CONTENT_TYPE,
+                        content_type.parse().map_err(serde::de::Error::custom)?,
+                    );
                 }
-                if let Some(path) = path {
-                    uri.push_str(&path);
-                } else {
-                    uri.push('/');
+                let authorization = [
+                    http_headers.contains_key(http::header::AUTHORIZATION)
+                        || template
+                            .headers
+                            .iter()
+                            .any(|(name, _)| name == http::header::AUTHORIZATION),
+                    basic_auth.is_some(),
+                    bearer_token_file.is_some(),
+                ];
+                if authorization.into_iter().filter(|given| *given).count() > 1 {
+                    return Err(serde::de::Error::custom(
+                        "at most one of basic_auth, bearer_token_file and an authorization header can be given",
+                    ));
                 }
+                template.basic_auth =
+                    basic_auth.map(|BasicAuth { username, password }| (username, password));
+                template.bearer_token_file = bearer_token_file;
+                let uris = if let Some(uris) = uris {
+                    if host.is_some() || scheme.is_some() || path.is_some() || port.is_some() {
+                        return Err(serde::de::Error::custom(
+                            "uris cannot be combined with host, scheme, path or port",
+                        ));
+                    }
+                    let uris = uris
+                        .into_iter()
+                        .map(|uri| match uri {
+                            WeightedUri::Uri(uri) => (uri, 1),
+                            WeightedUri::Weighted { uri, weight } => (uri, weight),
+                        })
+                        .collect::<Vec<_>>();
+                    if uris.is_empty() || uris.iter().any(|(_, weight)| *weight == 0) {
+                        return Err(serde::de::Error::custom(
+                            "uris must be non-empty with positive weights",
+                        ));
+                    }
+                    uris
+                } else {
+                    vec![(http_uri(host, scheme, path, port)?, 1)]
+                };
                 Ok(Self::HttpGet {
-                    uri: uri.parse().map_err(serde::de::Error::custom)?,
-                    headers: http_headers.unwrap_or_default(),
+                    uris,
+                    selection,
+                    method: http_method.unwrap_or(if body.is_some() {
+                        http::Method::POST
+                    } else {
+                        http::Method::GET
+                    }),
+                    headers: http_headers,
+                    template,
+                    pending_codes: pending_codes
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(http::StatusCode::from_u16)
+                        .collect::<Result<_, _>>()
+                        .map_err(serde::de::Error::custom)?,
+                    expected_status: match expected_status {
+                        Some(ExpectedStatus::One(range)) => Some(vec![range.parse()?]),
+                        Some(ExpectedStatus::Many(ranges)) => Some(
+                            ranges
+                                .into_iter()
+                                .map(StatusRange::parse)
+                                .collect::<Result<_, _>>()?,
+                        ),
+                        None => None,
+                    },
+                    body_regex: body_regex
+                        .as_deref()
+                        .map(regex::Regex::new)
+                        .transpose()
+                        .map_err(serde::de::Error::custom)?,
+                    json_path: match (json_path, json_value) {
+                        (Some(path), Some(value)) => Some((path, value)),
+                        (None, None) => None,
+                        (Some(_), None) => {
+                            return Err(serde::de::Error::missing_field("json_value"));
+                        }
+                        (None, Some(_)) => {
+                            return Err(serde::de::Error::missing_field("json_path"));
+                        }
+                    },
+                    redirect: follow_redirects.then_some(super::Redirect {
+                        allow_downgrade: allow_downgrade_redirect,
+                        max: max_redirects.unwrap_or(super::MAX_REDIRECTS),
+                        cookies: redirect_cookies,
+                    }),
+                    retry_on_codes: retry_on_codes
+                        .map(|codes| {
+                            codes
+                                .into_iter()
+                                .map(http::StatusCode::from_u16)
+                                .collect::<Result<_, _>>()
+                        })
+                        .transpose()
+                        .map_err(serde::de::Error::custom)?,
+                    validator_command: match validator_command {
+                        Some(mut command) if !command.is_empty() => {
+                            Some((command.remove(0), command))
+                        }
+                        Some(command) => {
+                            return Err(serde::de::Error::invalid_length(
+                                command.len(),
+                                &"one or more",
+                            ));
+                        }
+                        None => None,
+                    },
+                    connect_timeout: connect_timeout_seconds,
+                    insecure_skip_tls_verify,
+                    http_version,
                 })
             }
+            Method::CertFile { path, min_days } => Ok(Self::CertFile {
+                path,
+                min_days: min_days.unwrap_or(0),
+            }),
+            Method::NtpOffset {
+                server,
+                port,
+                max_offset_seconds,
+            } => Ok(Self::NtpOffset {
+                server: (server, port.unwrap_or(123)),
+                max_offset: max_offset_seconds,
+            }),
+            Method::EnvPresent { name } => Ok(Self::EnvPresent { name }),
+            Method::SecretPresent { path } => Ok(Self::SecretPresent { path }),
+            Method::Grpc {
+                host,
+                port,
+                service,
+            } => Ok(Self::Grpc {
+                server: (host.unwrap_or_else(|| "localhost".to_string()), port),
+                service,
+            }),
+            Method::Tcp { host, port } => Ok(Self::Tcp {
+                server: (host.unwrap_or_else(|| "localhost".to_string()), port),
+            }),
+            Method::Dns { host, expect } => Ok(Self::Dns { host, expect }),
+            Method::TcpExchange {
+                host,
+                port,
+                send,
+                send_hex,
+                expect_prefix,
+                expect_regex,
+            } => Ok(Self::TcpExchange {
+                server: (host.unwrap_or_else(|| "localhost".to_string()), port),
+                send: match (send, send_hex) {
+                    (Some(send), None) => send.into_bytes(),
+                    (None, Some(send_hex)) => hex(&send_hex).ok_or_else(|| {
+                        serde::de::Error::invalid_value(
+                            serde::de::Unexpected::Str(&send_hex),
+                            &"a hex string",
+                        )
+                    })?,
+                    (None, None) => Vec::new(),
+                    (Some(_), Some(_)) => {
+                        return Err(serde::de::Error::custom(
+                            "send and send_hex are mutually exclusive",
+                        ));
+                    }
+                },
+                expect: match (expect_prefix, expect_regex) {
+                    (Some(prefix), None) => Some(super::Expect::Prefix(prefix.into_bytes())),
+                    (None, Some(regex)) => Some(super::Expect::Regex(
+                        regex::bytes::Regex::new(&regex).map_err(serde::de::Error::custom)?,
+                    )),
+                    (None, None) => None,
+                    (Some(_), Some(_)) => {
+                        return Err(serde::de::Error::custom(
+                            "expect_prefix and expect_regex are mutually exclusive",
+                        ));
+                    }
+                },
+            }),
+            Method::Redis {
+                host,
+                port,
+                password,
+                db,
+                expect_role,
+            } => Ok(Self::Redis {
+                server: (
+                    host.unwrap_or_else(|| "localhost".to_string()),
+                    port.unwrap_or(6379),
+                ),
+                password,
+                db,
+                expect_role,
+            }),
+            Method::Postgres { connection_string } => Ok(Self::Postgres { connection_string }),
+            Method::FileDescriptors {
+                scope,
+                max_open,
+                max_percent,
+            } => Ok(Self::FileDescriptors {
+                scope,
+                limit: match (max_open, max_percent) {
+                    (Some(open), None) => super::FdLimit::Open(open),
+                    (None, Some(percent)) => super::FdLimit::Percent(percent),
+                    _ => {
+                        return Err(serde::de::Error::custom(
+                            "exactly one of max_open and max_percent is required",
+                        ));
+                    }
+                },
+            }),
+            Method::External { ttl_seconds } => Ok(Self::External {
+                ttl: ttl_seconds,
+                pushed: Default::default(),
+            }),
+        }
+    }
+}
+
+fn hex(s: &str) -> Option<Vec<u8>> {
+    let s = s.as_bytes();
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    s.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+impl<'de> Deserialize<'de> for super::Template {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Template {
+            headers: Option<BTreeMap<String, super::template::Text>>,
+            body: Option<super::template::Text>,
         }
+
+        let value = Template::deserialize(deserializer)?;
+        Ok(Self {
+            headers: value
+                .headers
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(name, value)| Ok((name.parse().map_err(serde::de::Error::custom)?, value)))
+                .collect::<Result<_, _>>()?,
+            body: value.body,
+            basic_auth: None,
+            bearer_token_file: None,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for super::template::Text {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        Self::parse(&value).map_err(serde::de::Error::custom)
     }
 }
@@ -0,0 +1,132 @@
+use bytes::{Buf, Bytes};
+use http_body_util::BodyExt;
+
+// https://github.com/grpc/grpc/blob/master/doc/health-checking.md
+pub const PATH: &str = "/grpc.health.v1.Health/Check";
+
+// https://github.com/grpc/grpc-proto/blob/master/grpc/health/v1/health.proto
+#[derive(Debug, PartialEq)]
+pub enum ServingStatus {
+    Unknown,
+    Serving,
+    NotServing,
+    ServiceUnknown,
+    Other(u64),
+}
+
+// a length-prefixed HealthCheckRequest message
+// https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md#requests
+pub fn request(service: &str) -> Bytes {
+    let mut message = Vec::new();
+    if !service.is_empty() {
+        // field 1, length-delimited
+        message.push(0x0a);
+        varint(&mut message, service.len() as u64);
+        message.extend_from_slice(service.as_bytes());
+    }
+    let mut frame = vec![0];
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&message);
+    frame.into()
+}
+
+// the status is carried in the trailers, or in the headers of a trailers-only response
+pub async fn response<B>(response: http::Response<B>) -> anyhow::Result<ServingStatus>
+where
+    B: http_body::Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let (parts, body) = response.into_parts();
+    if !parts.status.is_success() {
+        anyhow::bail!("unexpected status {}", parts.status);
+    }
+    let collected = http_body_util::Limited::new(body, super::BODY_LIMIT)
+        .collect()
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    let trailers = collected.trailers().cloned().unwrap_or_default();
+    let status = |name| {
+        trailers
+            .get(name)
+            .or_else(|| parts.headers.get(name))
+            .and_then(|value| value.to_str().ok())
+    };
+    match status("grpc-status") {
+        Some("0") => {}
+        Some(code) => anyhow::bail!(
+            "grpc-status {code}: {}",
+            status("grpc-message").unwrap_or_default(),
+        ),
+        None => anyhow::bail!("no grpc-status"),
+    }
+
+    let mut body = collected.to_bytes();
+    if body.len() < 5 {
+        anyhow::bail!("truncated response");
+    }
+    if body.get_u8() != 0 {
+        anyhow::bail!("compressed responses are not supported");
+    }
+    let len = body.get_u32() as usize;
+    if body.len() < len {
+        anyhow::bail!("truncated response");
+    }
+    let mut message = body.split_to(len);
+    let mut status = 0;
+    while message.has_remaining() {
+        let key = read_varint(&mut message)?;
+        match key & 0x7 {
+            0 => {
+                let value = read_varint(&mut message)?;
+                if key >> 3 == 1 {
+                    status = value;
+                }
+            }
+            1 => skip(&mut message, 8)?,
+            2 => {
+                let len = read_varint(&mut message)?;
+                skip(&mut message, len as usize)?;
+            }
+            5 => skip(&mut message, 4)?,
+            wire_type => anyhow::bail!("unexpected wire type {wire_type}"),
+        }
+    }
+    Ok(match status {
+        0 => ServingStatus::Unknown,
+        1 => ServingStatus::Serving,
+        2 => ServingStatus::NotServing,
+        3 => ServingStatus::ServiceUnknown,
+        status => ServingStatus::Other(status),
+    })
+}
+
+fn varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_varint(buf: &mut Bytes) -> anyhow::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        if !buf.has_remaining() {
+            break;
+        }
+        let byte = buf.get_u8();
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    anyhow::bail!("malformed varint")
+}
+
+fn skip(buf: &mut Bytes, len: usize) -> anyhow::Result<()> {
+    if buf.len() < len {
+        anyhow::bail!("truncated message");
+    }
+    buf.advance(len);
+    Ok(())
+}
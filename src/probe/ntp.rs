@@ -0,0 +1,71 @@
+use std::time::SystemTime;
+
+// seconds between 1900-01-01 (NTP era 0) and 1970-01-01
+const UNIX_OFFSET: u64 = 2_208_988_800;
+
+// https://datatracker.ietf.org/doc/html/rfc4330
+pub async fn offset(host: &str, port: u16) -> anyhow::Result<f64> {
+    let addr = tokio::net::lookup_host((host, port))
+        .await?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("no addresses found for {host}"))?;
+    let socket = tokio::net::UdpSocket::bind(if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    })
+    .await?;
+    socket.connect(addr).await?;
+
+    let mut request = [0; 48];
+    // LI = 0, VN = 4, Mode = 3 (client)
+    request[0] = 0x23;
+    let t1 = SystemTime::now();
+    request[40..48].copy_from_slice(&encode(t1)?);
+    socket.send(&request).await?;
+
+    let mut response = [0; 48];
+    loop {
+        let len = socket.recv(&mut response).await?;
+        let t4 = SystemTime::now();
+        // ignore stray datagrams that do not answer this request
+        if len < 48 || response[24..32] != request[40..48] {
+            continue;
+        }
+        if response[0] & 0x07 != 4 {
+            anyhow::bail!("unexpected mode {}", response[0] & 0x07);
+        }
+        if response[1] == 0 {
+            anyhow::bail!(
+                "kiss-o'-death {:?}",
+                String::from_utf8_lossy(&response[12..16])
+            );
+        }
+        let t2 = decode(&response[32..40]);
+        let t3 = decode(&response[40..48]);
+        let t1 = seconds(t1)?;
+        let t4 = seconds(t4)?;
+        break Ok(((t2 - t1) + (t3 - t4)) / 2.);
+    }
+}
+
+fn seconds(time: SystemTime) -> anyhow::Result<f64> {
+    Ok(time.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64())
+}
+
+fn encode(time: SystemTime) -> anyhow::Result<[u8; 8]> {
+    let since = time.duration_since(SystemTime::UNIX_EPOCH)?;
+    let secs = (since.as_secs() + UNIX_OFFSET) as u32;
+    let frac = ((since.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    let mut bytes = [0; 8];
+    bytes[..4].copy_from_slice(&secs.to_be_bytes());
+    bytes[4..].copy_from_slice(&(frac as u32).to_be_bytes());
+    Ok(bytes)
+}
+
+// seconds since the Unix epoch
+fn decode(bytes: &[u8]) -> f64 {
+    let secs = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as f64;
+    let frac = u32::from_be_bytes(bytes[4..].try_into().unwrap()) as f64;
+    secs - UNIX_OFFSET as f64 + frac / (1u64 << 32) as f64
+}
@@ -0,0 +1,110 @@
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Master,
+    #[serde(alias = "slave")]
+    Replica,
+    Sentinel,
+}
+
+// https://redis.io/docs/latest/develop/reference/protocol-spec/
+#[derive(Debug)]
+enum Value {
+    Simple(String),
+    Integer(#[allow(dead_code)] i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Value>>),
+}
+
+pub struct Connection(BufReader<TcpStream>);
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Self {
+        Self(BufReader::new(stream))
+    }
+
+    // error replies such as LOADING are returned as errors
+    async fn command(&mut self, args: &[&[u8]]) -> anyhow::Result<Value> {
+        let mut request = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            request.extend_from_slice(arg);
+            request.extend_from_slice(b"\r\n");
+        }
+        self.0.get_mut().write_all(&request).await?;
+        self.read().await
+    }
+
+    async fn read(&mut self) -> anyhow::Result<Value> {
+        let mut line = String::new();
+        if self.0.read_line(&mut line).await? == 0 {
+            anyhow::bail!("connection closed");
+        }
+        let line = line
+            .strip_suffix("\r\n")
+            .ok_or_else(|| anyhow::anyhow!("malformed reply {line:?}"))?;
+        let (kind, data) = line.split_at_checked(1).unwrap_or(("", ""));
+        match kind {
+            "+" => Ok(Value::Simple(data.to_string())),
+            "-" => anyhow::bail!("{data}"),
+            ":" => Ok(Value::Integer(data.parse()?)),
+            "$" => match data.parse::<i64>()? {
+                -1 => Ok(Value::Bulk(None)),
+                len => {
+                    let mut buf = vec![0; usize::try_from(len)? + 2];
+                    self.0.read_exact(&mut buf).await?;
+                    buf.truncate(buf.len() - 2);
+                    Ok(Value::Bulk(Some(buf)))
+                }
+            },
+            "*" => match data.parse::<i64>()? {
+                -1 => Ok(Value::Array(None)),
+                len => {
+                    let mut values = Vec::new();
+                    for _ in 0..len {
+                        values.push(Box::pin(self.read()).await?);
+                    }
+                    Ok(Value::Array(Some(values)))
+                }
+            },
+            _ => anyhow::bail!("malformed reply {line:?}"),
+        }
+    }
+
+    pub async fn auth(&mut self, password: &str) -> anyhow::Result<()> {
+        self.command(&[b"AUTH", password.as_bytes()]).await?;
+        Ok(())
+    }
+
+    pub async fn select(&mut self, db: u32) -> anyhow::Result<()> {
+        self.command(&[b"SELECT", db.to_string().as_bytes()])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn ping(&mut self) -> anyhow::Result<()> {
+        match self.command(&[b"PING"]).await? {
+            Value::Simple(pong) if pong == "PONG" => Ok(()),
+            value => anyhow::bail!("unexpected reply {value:?} to PING"),
+        }
+    }
+
+    // https://redis.io/docs/latest/commands/role/
+    pub async fn role(&mut self) -> anyhow::Result<Role> {
+        let value = self.command(&[b"ROLE"]).await?;
+        if let Value::Array(Some(values)) = &value
+            && let Some(Value::Bulk(Some(role))) = values.first()
+        {
+            match &role[..] {
+                b"master" => return Ok(Role::Master),
+                b"slave" => return Ok(Role::Replica),
+                b"sentinel" => return Ok(Role::Sentinel),
+                _ => {}
+            }
+        }
+        anyhow::bail!("unexpected reply {value:?} to ROLE")
+    }
+}
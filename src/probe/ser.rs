@@ -0,0 +1,389 @@
+use serde::{Serialize, Serializer};
+use std::path::Path;
+
+impl Serialize for super::Probe {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct Probe<'a> {
+            #[serde(flatten)]
+            method: &'a super::Method,
+            initial_delay_seconds: f64,
+            period_seconds: f64,
+            period_jitter: f64,
+            timeout_seconds: f64,
+            success_threshold: usize,
+            failure_threshold: usize,
+            hang_threshold: Option<usize>,
+            cold_checks: usize,
+            retries: usize,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            window: Option<usize>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            fail_in_window: Option<usize>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            success_in_window: Option<usize>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            result_ttl_seconds: Option<f64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            result_ttl_policy: Option<super::StalePolicy>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            on_success: Option<Vec<&'a str>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            on_failure: Option<Vec<&'a str>>,
+            require_startup: bool,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            budget_seconds: Option<f64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            backoff_multiplier: Option<f64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            max_backoff_seconds: Option<f64>,
+            missed_schedule: super::MissedSchedule,
+        }
+
+        fn command(command: &Option<(String, Vec<String>)>) -> Option<Vec<&str>> {
+            command.as_ref().map(|(program, args)| {
+                [program]
+                    .into_iter()
+                    .chain(args)
+                    .map(String::as_str)
+                    .collect()
+            })
+        }
+
+        Probe {
+            method: &self.method,
+            initial_delay_seconds: self.initial_delay.as_secs_f64(),
+            period_seconds: self.period.as_secs_f64(),
+            period_jitter: self.period_jitter,
+            timeout_seconds: self.timeout.as_secs_f64(),
+            success_threshold: self.success_threshold,
+            failure_threshold: self.failure_threshold,
+            hang_threshold: self.hang_threshold,
+            cold_checks: self.cold_checks,
+            retries: self.retries,
+            window: self.window.as_ref().map(|window| window.size),
+            fail_in_window: self.window.as_ref().map(|window| window.failures),
+            success_in_window: self.window.as_ref().map(|window| window.successes),
+            result_ttl_seconds: self.result_ttl.map(|(ttl, _)| ttl.as_secs_f64()),
+            result_ttl_policy: self.result_ttl.map(|(_, policy)| policy),
+            on_success: command(&self.on_success),
+            on_failure: command(&self.on_failure),
+            require_startup: self.require_startup,
+            budget_seconds: self.budget.map(|budget| budget.as_secs_f64()),
+            backoff_multiplier: self.backoff.map(|(multiplier, _)| multiplier),
+            max_backoff_seconds: self.backoff.map(|(_, max)| max.as_secs_f64()),
+            missed_schedule: self.missed_schedule,
+        }
+        .serialize(serializer)
+    }
+}
+
+// header and environment values, templates, passwords and connection strings are left out since they may contain secrets
+impl Serialize for super::Method {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        #[serde(rename_all = "snake_case")]
+        enum Method<'a> {
+            Exec {
+                command: Vec<&'a str>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                nagios: Option<Nagios>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                retry_stderr_regex: Option<&'a str>,
+                env: Vec<&'a str>,
+                clear_env: bool,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                working_dir: Option<&'a Path>,
+                success_exit_codes: &'a [i32],
+                #[serde(skip_serializing_if = "Option::is_none")]
+                stdout_regex: Option<&'a str>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                stderr_regex: Option<&'a str>,
+            },
+            HttpGet {
+                uris: Vec<WeightedUri>,
+                selection: super::Selection,
+                http_method: &'a str,
+                http_headers: Vec<&'a str>,
+                pending_codes: Vec<u16>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                expected_status: Option<Vec<String>>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                body_regex: Option<&'a str>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                json_path: Option<&'a serde_json_path::JsonPath>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                json_value: Option<&'a serde_json::Value>,
+                follow_redirects: bool,
+                allow_downgrade_redirect: bool,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                max_redirects: Option<usize>,
+                redirect_cookies: bool,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                retry_on_codes: Option<Vec<u16>>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                validator_command: Option<Vec<&'a str>>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                connect_timeout_seconds: Option<f64>,
+                insecure_skip_tls_verify: bool,
+                http_version: super::hyper::HttpVersion,
+            },
+            CertFile {
+                path: &'a Path,
+                min_days: u64,
+            },
+            #[serde(rename = "ntp")]
+            NtpOffset {
+                server: &'a str,
+                port: u16,
+                max_offset_seconds: f64,
+            },
+            EnvPresent {
+                name: &'a str,
+            },
+            SecretPresent {
+                path: &'a Path,
+            },
+            Grpc {
+                host: &'a str,
+                port: u16,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                service: Option<&'a str>,
+            },
+            #[serde(rename = "tcp_socket")]
+            Tcp {
+                host: &'a str,
+                port: u16,
+            },
+            Dns {
+                host: &'a str,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                expect: Option<&'a [std::net::IpAddr]>,
+            },
+            TcpExchange {
+                host: &'a str,
+                port: u16,
+                send_hex: String,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                expect_prefix: Option<String>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                expect_regex: Option<&'a str>,
+            },
+            Redis {
+                host: &'a str,
+                port: u16,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                db: Option<u32>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                expect_role: Option<super::Role>,
+            },
+            Postgres {},
+            #[serde(rename = "fd")]
+            FileDescriptors {
+                scope: super::FdScope,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                max_open: Option<u64>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                max_percent: Option<f64>,
+            },
+            External {
+                ttl_seconds: f64,
+            },
+        }
+
+        #[derive(Serialize)]
+        struct WeightedUri {
+            uri: String,
+            weight: u32,
+        }
+
+        #[derive(Serialize)]
+        struct Nagios {
+            warning_ok: bool,
+        }
+
+        match self {
+            Self::Exec {
+                command: (program, args),
+                nagios,
+                retry_stderr_regex,
+                env,
+                clear_env,
+                working_dir,
+                success_exit_codes,
+                stdout_regex,
+                stderr_regex,
+            } => Method::Exec {
+                command: [program]
+                    .into_iter()
+                    .chain(args)
+                    .map(String::as_str)
+                    .collect(),
+                nagios: nagios.as_ref().map(|nagios| Nagios {
+                    warning_ok: nagios.warning_ok,
+                }),
+                retry_stderr_regex: retry_stderr_regex.as_ref().map(regex::Regex::as_str),
+                env: env.keys().map(String::as_str).collect(),
+                clear_env: *clear_env,
+                working_dir: working_dir.as_deref(),
+                success_exit_codes,
+                stdout_regex: stdout_regex.as_ref().map(regex::Regex::as_str),
+                stderr_regex: stderr_regex.as_ref().map(regex::Regex::as_str),
+            },
+            Self::HttpGet {
+                uris,
+                selection,
+                method,
+                headers,
+                template,
+                pending_codes,
+                expected_status,
+                body_regex,
+                json_path,
+                redirect,
+                retry_on_codes,
+                validator_command,
+                connect_timeout,
+                insecure_skip_tls_verify,
+                http_version,
+            } => Method::HttpGet {
+                uris: uris
+                    .iter()
+                    .map(|(uri, weight)| WeightedUri {
+                        uri: uri.to_string(),
+                        weight: *weight,
+                    })
+                    .collect(),
+                selection: *selection,
+                http_method: method.as_str(),
+                http_headers: headers
+                    .keys()
+                    .chain(template.headers.iter().map(|(name, _)| name))
+                    .chain(
+                        template
+                            .basic_auth
+                            .is_some()
+                            .then_some(&http::header::AUTHORIZATION),
+                    )
+                    .chain(
+                        template
+                            .bearer_token_file
+                            .is_some()
+                            .then_some(&http::header::AUTHORIZATION),
+                    )
+                    .map(http::HeaderName::as_str)
+                    .collect(),
+                pending_codes: pending_codes.iter().map(http::StatusCode::as_u16).collect(),
+                expected_status: expected_status.as_ref().map(|ranges| {
+                    ranges
+                        .iter()
+                        .map(|range| format!("{}-{}", range.start(), range.end()))
+                        .collect()
+                }),
+                body_regex: body_regex.as_ref().map(regex::Regex::as_str),
+                json_path: json_path.as_ref().map(|(path, _)| path),
+                json_value: json_path.as_ref().map(|(_, value)| value),
+                follow_redirects: redirect.is_some(),
+                allow_downgrade_redirect: redirect
+                    .as_ref()
+                    .is_some_and(|redirect| redirect.allow_downgrade),
+                max_redirects: redirect.as_ref().map(|redirect| redirect.max),
+                redirect_cookies: redirect.as_ref().is_some_and(|redirect| redirect.cookies),
+                retry_on_codes: retry_on_codes
+                    .as_ref()
+                    .map(|codes| codes.iter().map(http::StatusCode::as_u16).collect()),
+                validator_command: validator_command.as_ref().map(|(program, args)| {
+                    [program]
+                        .into_iter()
+                        .chain(args)
+                        .map(String::as_str)
+                        .collect()
+                }),
+                connect_timeout_seconds: connect_timeout.map(|timeout| timeout.as_secs_f64()),
+                insecure_skip_tls_verify: *insecure_skip_tls_verify,
+                http_version: *http_version,
+            },
+            Self::CertFile { path, min_days } => Method::CertFile {
+                path,
+                min_days: *min_days,
+            },
+            Self::NtpOffset {
+                server: (host, port),
+                max_offset,
+            } => Method::NtpOffset {
+                server: host,
+                port: *port,
+                max_offset_seconds: max_offset.as_secs_f64(),
+            },
+            Self::EnvPresent { name } => Method::EnvPresent { name },
+            Self::SecretPresent { path } => Method::SecretPresent { path },
+            Self::Grpc {
+                server: (host, port),
+                service,
+            } => Method::Grpc {
+                host,
+                port: *port,
+                service: service.as_deref(),
+            },
+            Self::Tcp {
+                server: (host, port),
+            } => Method::Tcp { host, port: *port },
+            Self::Dns { host, expect } => Method::Dns {
+                host,
+                expect: expect.as_deref(),
+            },
+            Self::TcpExchange {
+                server: (host, port),
+                send,
+                expect,
+            } => Method::TcpExchange {
+                host,
+                port: *port,
+                send_hex: send.iter().map(|byte| format!("{byte:02x}")).collect(),
+                expect_prefix: match expect {
+                    Some(super::Expect::Prefix(prefix)) => {
+                        Some(String::from_utf8_lossy(prefix).into_owned())
+                    }
+                    _ => None,
+                },
+                expect_regex: match expect {
+                    Some(super::Expect::Regex(regex)) => Some(regex.as_str()),
+                    _ => None,
+                },
+            },
+            Self::Redis {
+                server: (host, port),
+                db,
+                expect_role,
+                ..
+            } => Method::Redis {
+                host,
+                port: *port,
+                db: *db,
+                expect_role: *expect_role,
+            },
+            Self::Postgres { .. } => Method::Postgres {},
+            Self::FileDescriptors { scope, limit } => Method::FileDescriptors {
+                scope: *scope,
+                max_open: match limit {
+                    super::FdLimit::Open(open) => Some(*open),
+                    super::FdLimit::Percent(_) => None,
+                },
+                max_percent: match limit {
+                    super::FdLimit::Open(_) => None,
+                    super::FdLimit::Percent(percent) => Some(*percent),
+                },
+            },
+            Self::External { ttl, .. } => Method::External {
+                ttl_seconds: ttl.as_secs_f64(),
+            },
+        }
+        .serialize(serializer)
+    }
+}
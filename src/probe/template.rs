@@ -0,0 +1,74 @@
+use anyhow::Context;
+use std::path::PathBuf;
+
+// request parts rendered on every call, so that rotated secrets are picked up
+// headers are applied after the static `http_headers`, overriding them
+#[derive(Clone, Debug, Default)]
+pub struct Template {
+    pub headers: Vec<(http::HeaderName, Text)>,
+    pub body: Option<Text>,
+    // username and password, sent as `authorization: Basic ...`
+    pub basic_auth: Option<(String, Text)>,
+    // sent as `authorization: Bearer ...`, read on every call since tokens are short-lived
+    pub bearer_token_file: Option<PathBuf>,
+}
+
+// a string with `{{env:NAME}}` and `{{file:PATH}}` placeholders
+#[derive(Clone, Debug)]
+pub struct Text(Vec<Segment>);
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Env(String),
+    File(PathBuf),
+}
+
+impl Text {
+    // taken as is, without placeholders
+    pub fn literal(s: String) -> Self {
+        Self(vec![Segment::Literal(s)])
+    }
+
+    pub fn parse(mut s: &str) -> anyhow::Result<Self> {
+        let mut segments = Vec::new();
+        while let Some(start) = s.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Literal(s[..start].to_string()));
+            }
+            let end = s[start..].find("}}").context("unterminated placeholder")? + start;
+            let placeholder = s[start + 2..end].trim();
+            match placeholder.split_once(':') {
+                Some(("env", name)) => segments.push(Segment::Env(name.to_string())),
+                Some(("file", path)) => segments.push(Segment::File(path.into())),
+                _ => anyhow::bail!("unknown placeholder {placeholder:?}"),
+            }
+            s = &s[end + 2..];
+        }
+        if !s.is_empty() {
+            segments.push(Segment::Literal(s.to_string()));
+        }
+        Ok(Self(segments))
+    }
+
+    // the rendered value may contain secrets; never log it
+    pub async fn render(&self) -> anyhow::Result<String> {
+        let mut output = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(literal) => output.push_str(literal),
+                Segment::Env(name) => output.push_str(
+                    &std::env::var(name)
+                        .with_context(|| format!("failed to read environment variable {name}"))?,
+                ),
+                Segment::File(path) => output.push_str(
+                    tokio::fs::read_to_string(path)
+                        .await
+                        .with_context(|| format!("failed to read {}", path.display()))?
+                        .trim_end_matches(['\r', '\n']),
+                ),
+            }
+        }
+        Ok(output)
+    }
+}
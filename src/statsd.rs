@@ -0,0 +1,85 @@
+use std::io;
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Format {
+    // tags are folded into the metric name
+    Statsd,
+    // https://docs.datadoghq.com/developers/dogstatsd/datagram_shell/
+    Dogstatsd,
+}
+
+pub struct Statsd {
+    socket: UdpSocket,
+    format: Format,
+}
+
+impl Statsd {
+    pub fn connect(addr: &str, format: Format) -> io::Result<Self> {
+        let socket = UdpSocket::bind("[::]:0").or_else(|_| UdpSocket::bind("0.0.0.0:0"))?;
+        socket.connect(addr)?;
+        // a slow or missing collector never holds up probing
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket, format })
+    }
+
+    pub fn sink(self: &Arc<Self>, tags: &[(&str, &str)]) -> Sink {
+        Sink {
+            statsd: self.clone(),
+            tags: tags
+                .iter()
+                .map(|(key, value)| (key.to_string(), sanitize(value)))
+                .collect(),
+        }
+    }
+}
+
+// metrics of a single probe
+pub struct Sink {
+    statsd: Arc<Statsd>,
+    tags: Vec<(String, String)>,
+}
+
+impl Sink {
+    pub fn count(&self, name: &str, tags: &[(&str, &str)]) {
+        self.send(name, "1|c", tags);
+    }
+
+    pub fn timing(&self, name: &str, duration: Duration) {
+        self.send(name, &format!("{}|ms", duration.as_secs_f64() * 1000.), &[]);
+    }
+
+    fn send(&self, name: &str, value: &str, tags: &[(&str, &str)]) {
+        let tags = self
+            .tags
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.clone()))
+            .chain(tags.iter().map(|(key, value)| (*key, sanitize(value))));
+        let datagram = match self.statsd.format {
+            Format::Statsd => {
+                let mut metric = "healthzd".to_string();
+                for (_, value) in tags {
+                    metric.push('.');
+                    metric.push_str(&value);
+                }
+                format!("{metric}.{name}:{value}")
+            }
+            Format::Dogstatsd => format!(
+                "healthzd.{name}:{value}|#{}",
+                tags.map(|(key, value)| format!("{key}:{value}"))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+        };
+        // e.g. refused while the collector is down
+        if let Err(e) = self.statsd.socket.send(datagram.as_bytes()) {
+            tracing::debug!(error = e.to_string(), "failed to send metric");
+        }
+    }
+}
+
+fn sanitize(value: &str) -> String {
+    value.replace(['.', ':', '|', '#', ',', ' '], "_")
+}
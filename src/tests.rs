@@ -17,25 +17,11 @@ impl Fixture {
     fn new(with_liveness: bool, with_readiness: bool, with_startup: bool) -> Self {
         fn probe(path: &Path) -> probe::Probe {
             probe::Probe {
-                method: probe::Method::Exec {
-                    command: (
-                        "test".to_string(),
-                        vec!["-f".to_string(), path.display().to_string()],
-                    ),
-                },
-                initial_delay: Duration::default(),
-                period: Duration::from_millis(100),
                 timeout: Duration::from_millis(10),
-                success_threshold: 1,
-                failure_threshold: 1,
+                ..exec_probe(&["test", "-f", &path.display().to_string()])
             }
         }
 
-        let tls_config = hyper::tls_config().unwrap();
-        let context = probe::Context {
-            client: hyper::client(tls_config),
-        };
-
         let temp = tempfile::tempdir().unwrap();
         let liveness = temp.path().join("liveness");
         let readiness = temp.path().join("readiness");
@@ -43,14 +29,18 @@ impl Fixture {
 
         let target = super::Target {
             name: "test".to_string(),
+            group: None,
             liveness_probe: with_liveness.then(|| probe(&liveness)),
             readiness_probe: with_readiness.then(|| probe(&readiness)),
             startup_probe: with_startup.then(|| probe(&startup)),
+            startup_deadline_seconds: None,
+            startup_phase: 0,
+            ready_window: None,
         };
 
         Self {
             _temp: temp,
-            context,
+            context: context(),
             target,
             status: super::Status::default(),
             liveness,
@@ -60,7 +50,7 @@ impl Fixture {
     }
 
     fn update(&self) -> impl Future<Output = ()> + '_ {
-        super::update(&self.context, &self.target, &self.status)
+        super::update(&self.context, &self.target, &self.status, async {})
     }
 
     async fn liveness(&self, value: bool) {
@@ -88,6 +78,99 @@ impl Fixture {
     }
 }
 
+fn context() -> probe::Context {
+    probe::Context::new(
+        hyper::tls_config(&[], true, None).unwrap(),
+        hyper::Resolver::new(None),
+        hyper::ConnectOrder::default(),
+    )
+}
+
+// runs every 100ms, judged by each attempt on its own
+fn exec_probe(command: &[&str]) -> probe::Probe {
+    probe::Probe {
+        method: probe::Method::Exec {
+            command: (
+                command[0].to_string(),
+                command[1..].iter().map(ToString::to_string).collect(),
+            ),
+            nagios: None,
+            retry_stderr_regex: None,
+            env: Default::default(),
+            clear_env: false,
+            working_dir: None,
+            success_exit_codes: vec![0],
+            stdout_regex: None,
+            stderr_regex: None,
+        },
+        initial_delay: Duration::default(),
+        period: Duration::from_millis(100),
+        period_jitter: 0.0,
+        timeout: Duration::from_secs(1),
+        success_threshold: 1,
+        failure_threshold: 1,
+        hang_threshold: None,
+        cold_checks: 0,
+        retries: 0,
+        window: None,
+        result_ttl: None,
+        on_success: None,
+        on_failure: None,
+        require_startup: false,
+        budget: None,
+        backoff: None,
+        missed_schedule: probe::MissedSchedule::Skip,
+    }
+}
+
+// a throwaway CA, and certificates issued by it
+fn ca() -> rcgen::CertifiedIssuer<'static, rcgen::KeyPair> {
+    let mut params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    rcgen::CertifiedIssuer::self_signed(params, rcgen::KeyPair::generate().unwrap()).unwrap()
+}
+
+fn issue(
+    ca: &rcgen::Issuer<'_, rcgen::KeyPair>,
+    name: &str,
+) -> (rcgen::Certificate, rcgen::KeyPair) {
+    let key = rcgen::KeyPair::generate().unwrap();
+    let cert = rcgen::CertificateParams::new([name.to_string()])
+        .unwrap()
+        .signed_by(&key, ca)
+        .unwrap();
+    (cert, key)
+}
+
+// writes the certificate and key as PEM files to `dir`
+fn write_pem(
+    dir: &Path,
+    name: &str,
+    cert: &rcgen::Certificate,
+    key: &rcgen::KeyPair,
+) -> (PathBuf, PathBuf) {
+    let paths = (
+        dir.join(format!("{name}.crt")),
+        dir.join(format!("{name}.key")),
+    );
+    std::fs::write(&paths.0, cert.pem()).unwrap();
+    std::fs::write(&paths.1, key.serialize_pem()).unwrap();
+    paths
+}
+
+// serves `app` over TLS, returning the port
+async fn serve_tls(tls_config: rustls::ServerConfig, app: axum::Router) -> u16 {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(super::serve_tls(
+        listener,
+        tls_config,
+        app,
+        futures::future::pending(),
+    ));
+    port
+}
+
 #[tokio::test]
 async fn test_update_empty() {
     let fixture = Fixture::new(false, false, false);
@@ -102,7 +185,8 @@ async fn test_update_empty() {
 #[tokio::test]
 async fn test_update_liveness() {
     let fixture = Fixture::new(true, false, false);
-    futures::future::join(fixture.update(), async {
+    let (update, abort) = futures::future::abortable(fixture.update());
+    let _ = futures::future::join(update, async {
         assert!(fixture.status.live.load(Ordering::Relaxed));
         assert!(fixture.status.ready.load(Ordering::Relaxed));
 
@@ -111,18 +195,61 @@ async fn test_update_liveness() {
 
         assert!(fixture.status.live.load(Ordering::Relaxed));
         assert!(fixture.status.ready.load(Ordering::Relaxed));
+        assert!(fixture.status.live_transition.lock().unwrap().is_none());
 
         fixture.liveness(false).await;
         tokio::time::sleep(Duration::from_millis(200)).await;
 
         assert!(!fixture.status.live.load(Ordering::Relaxed));
         assert!(fixture.status.ready.load(Ordering::Relaxed));
+        assert!(fixture.status.live_transition.lock().unwrap().is_some());
 
+        let failed = *fixture.status.live_transition.lock().unwrap();
         fixture.liveness(true).await;
         tokio::time::sleep(Duration::from_millis(200)).await;
 
-        assert!(!fixture.status.live.load(Ordering::Relaxed));
+        // recovered
+        assert!(fixture.status.live.load(Ordering::Relaxed));
         assert!(fixture.status.ready.load(Ordering::Relaxed));
+        assert_ne!(*fixture.status.live_transition.lock().unwrap(), failed);
+
+        abort.abort();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_live_recovery() {
+    use tower::ServiceExt;
+
+    let fixture = Fixture::new(true, false, false);
+    let targets: super::Targets = std::sync::Arc::from([(
+        fixture.target.clone(),
+        std::sync::Arc::new(super::Status::default()),
+    )]);
+    let app = super::live_router(&tokio::sync::watch::channel(targets.clone()).1, &None);
+    let live = async || {
+        let request = http::Request::get("/live")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    };
+
+    let (update, abort) = futures::future::abortable(super::update(
+        &fixture.context,
+        &targets[0].0,
+        &targets[0].1,
+        async {},
+    ));
+    let _ = futures::future::join(update, async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(live().await, http::StatusCode::INTERNAL_SERVER_ERROR);
+
+        fixture.liveness(true).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(live().await, http::StatusCode::OK);
+
+        abort.abort();
     })
     .await;
 }
@@ -163,6 +290,108 @@ async fn test_update_readiness() {
     .await;
 }
 
+// log output written to memory
+#[derive(Clone, Default)]
+struct Capture(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Capture {
+    fn subscriber(
+        &self,
+        format: crate::log::Format,
+        filter: tracing_subscriber::EnvFilter,
+    ) -> impl tracing::Subscriber + Send + Sync {
+        let capture = self.clone();
+        crate::log::subscriber(format, filter, move || capture.clone())
+    }
+}
+
+#[test]
+fn test_log_level() {
+    let capture = Capture::default();
+    tracing::subscriber::with_default(
+        capture.subscriber(
+            crate::log::Format::Text,
+            crate::log::filter(crate::log::Level::Warn, None).unwrap(),
+        ),
+        || {
+            tracing::info!("suppressed");
+            tracing::warn!("kept");
+        },
+    );
+    let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+    assert!(!output.contains("suppressed"), "{output}");
+    assert!(output.contains("kept"), "{output}");
+
+    // RUST_LOG wins over the flag
+    let capture = Capture::default();
+    tracing::subscriber::with_default(
+        capture.subscriber(
+            crate::log::Format::Text,
+            crate::log::filter(crate::log::Level::Warn, Some("info")).unwrap(),
+        ),
+        || tracing::info!("shown"),
+    );
+    let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+    assert!(output.contains("shown"), "{output}");
+
+    assert!(crate::log::filter(crate::log::Level::Info, Some("=")).is_err());
+}
+
+#[tokio::test]
+async fn test_log_json() {
+    let capture = Capture::default();
+    let _guard = tracing::subscriber::set_default(capture.subscriber(
+        crate::log::Format::Json,
+        crate::log::filter(crate::log::Level::Info, None).unwrap(),
+    ));
+    let fixture = Fixture::new(true, false, false);
+    fixture.liveness(true).await;
+    let _ = tokio::time::timeout(Duration::from_millis(300), fixture.update()).await;
+
+    let output = capture.0.lock().unwrap().clone();
+    let lines = std::str::from_utf8(&output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    let ok = lines
+        .iter()
+        .find(|line| line["fields"]["message"] == "ok")
+        .unwrap();
+    assert_eq!(ok["level"], "INFO");
+    assert_eq!(
+        ok["spans"][0],
+        serde_json::json!({"name": "target", "target": "test"})
+    );
+    assert_eq!(ok["spans"][1]["name"], "liveness");
+    assert_eq!(ok["span"]["name"], "exec");
+    drop(_guard);
+
+    // renamed only in JSON
+    let capture = Capture::default();
+    let _guard = tracing::subscriber::set_default(capture.subscriber(
+        crate::log::Format::Text,
+        crate::log::filter(crate::log::Level::Info, None).unwrap(),
+    ));
+    let _ = tokio::time::timeout(Duration::from_millis(300), fixture.update()).await;
+    let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+    let output = regex::Regex::new("\x1b\\[[0-9;]*m")
+        .unwrap()
+        .replace_all(&output, "");
+    assert!(output.contains("target{name=\"test\"}"), "{output}");
+}
+
 #[tokio::test]
 async fn test_update_startup() {
     let fixture = Fixture::new(false, false, true);
@@ -190,6 +419,106 @@ async fn test_update_startup() {
     .await;
 }
 
+#[tokio::test]
+async fn test_update_startup_deadline() {
+    let mut fixture = Fixture::new(true, true, true);
+    fixture.target.startup_deadline_seconds = Some(Duration::from_millis(300));
+    futures::future::join(fixture.update(), async {
+        fixture.liveness(true).await;
+        fixture.readiness(true).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(fixture.status.live.load(Ordering::Relaxed));
+        assert!(!fixture.status.ready.load(Ordering::Relaxed));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(!fixture.status.live.load(Ordering::Relaxed));
+        assert!(!fixture.status.ready.load(Ordering::Relaxed));
+        assert_eq!(*fixture.status.started.borrow(), super::Startup::Failed);
+    })
+    .await;
+}
+
+#[test]
+fn test_startup_deadline_round_trip() {
+    let target =
+        super::parse_target(r#"{"name": "test", "startup_deadline_seconds": 1.5}"#).unwrap();
+    assert_eq!(
+        target.startup_deadline_seconds,
+        Some(Duration::from_millis(1500)),
+    );
+    assert_eq!(
+        serde_json::to_value(&target).unwrap()["startup_deadline_seconds"],
+        1.5,
+    );
+}
+
+#[tokio::test]
+async fn test_startupz() {
+    use tower::ServiceExt;
+
+    let fixture = Fixture::new(false, true, true);
+    let targets: std::sync::Arc<[_]> = std::sync::Arc::from([(
+        fixture.target.clone(),
+        std::sync::Arc::new(super::Status::default()),
+    )]);
+    let app = super::router(
+        &tokio::sync::watch::channel(targets.clone()).1,
+        &None,
+        &Default::default(),
+        &Default::default(),
+        false,
+        true,
+        true,
+    );
+    let startupz = async || {
+        let request = http::Request::get("/startupz")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    };
+
+    let (target, status) = &targets[0];
+    let (update, abort) =
+        futures::future::abortable(super::update(&fixture.context, target, status, async {}));
+    let _ = futures::future::join(update, async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(startupz().await, http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(*status.started.borrow(), super::Startup::Pending);
+
+        fixture.startup(true).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(startupz().await, http::StatusCode::OK);
+        assert_eq!(*status.started.borrow(), super::Startup::Complete);
+        abort.abort();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_update_hang() {
+    let mut fixture = Fixture::new(true, false, false);
+    let probe = fixture.target.liveness_probe.as_mut().unwrap();
+    probe.method = exec_probe(&["sleep", "1"]).method;
+    probe.failure_threshold = 100;
+    probe.hang_threshold = Some(2);
+    let (update, abort) = futures::future::abortable(fixture.update());
+    let _ = futures::future::join(update, async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(fixture.status.live.load(Ordering::Relaxed));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(!fixture.status.live.load(Ordering::Relaxed));
+        assert_eq!(fixture.status.liveness.hangs.load(Ordering::Relaxed), 2);
+
+        abort.abort();
+    })
+    .await;
+}
+
 #[tokio::test]
 async fn test_update_all() {
     let fixture = Fixture::new(true, true, true);
@@ -254,10 +583,3594 @@ async fn test_update_all() {
         fixture.readiness(true).await;
         tokio::time::sleep(Duration::from_millis(200)).await;
 
-        assert!(!fixture.status.live.load(Ordering::Relaxed));
+        assert!(fixture.status.live.load(Ordering::Relaxed));
         assert!(fixture.status.ready.load(Ordering::Relaxed));
 
         abort.abort();
     })
     .await;
 }
+
+#[tokio::test]
+async fn test_watchdog() {
+    let watchdog = std::sync::Arc::new(crate::watchdog::Watchdog::new(Duration::from_millis(100)));
+    watchdog.spawn();
+    let (beat, abort) = futures::future::abortable(watchdog.beat());
+    let _ = futures::future::join(beat, async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(watchdog.live());
+
+        // block the executor so that heartbeats stop
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(!watchdog.live());
+
+        // back once heartbeats resume
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(watchdog.live());
+
+        // a task that stops making progress while the executor keeps going
+        let mut stuck = Box::pin(watchdog.beat());
+        assert!(futures::poll!(&mut stuck).is_pending());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!watchdog.live());
+        drop(stuck);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(watchdog.live());
+
+        abort.abort();
+    })
+    .await;
+}
+
+#[test]
+fn test_parse_interval() {
+    assert_eq!(super::parse_interval("5"), Ok(Duration::from_secs(5)));
+    assert_eq!(
+        super::parse_interval("0"),
+        Err("must be greater than 0".to_string()),
+    );
+}
+
+#[test]
+fn test_parse_target_shared_method() {
+    let target = super::parse_target(
+        r#"{
+            "name": "test",
+            "method": {"exec": {"command": ["true"]}},
+            "liveness_probe": {"failure_threshold": 5},
+            "startup_probe": {"http_get": {"port": 8080}}
+        }"#,
+    )
+    .unwrap();
+
+    let liveness = target.liveness_probe.unwrap();
+    assert!(matches!(liveness.method, probe::Method::Exec { .. }));
+    assert_eq!(liveness.failure_threshold, 5);
+    assert!(target.readiness_probe.is_none());
+    let startup = target.startup_probe.unwrap();
+    assert!(matches!(startup.method, probe::Method::HttpGet { .. }));
+
+    assert!(super::parse_target(r#"{"name": "test", "liveness_probe": {}}"#).is_err());
+}
+
+#[test]
+fn test_conditions() {
+    let fixture = Fixture::new(false, true, false);
+    let targets = [(
+        fixture.target.clone(),
+        std::sync::Arc::new(super::Status::default()),
+    )];
+    *targets[0].1.readiness.last_error.lock().unwrap() = Some("exit status: 1".to_string());
+    assert_eq!(
+        serde_json::to_value(super::conditions(&targets)).unwrap(),
+        serde_json::json!([{
+            "type": "Ready",
+            "status": "False",
+            "reason": "TargetsNotReady",
+            "message": "targets with unready status: [test]; test: exit status: 1",
+        }]),
+    );
+
+    targets[0].1.ready.store(true, Ordering::Relaxed);
+    assert_eq!(
+        serde_json::to_value(super::conditions(&targets)).unwrap(),
+        serde_json::json!([{"type": "Ready", "status": "True"}]),
+    );
+}
+
+#[tokio::test]
+async fn test_status() {
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    let fixture = Fixture::new(true, true, true);
+    let targets: std::sync::Arc<[_]> = std::sync::Arc::from([(
+        fixture.target.clone(),
+        std::sync::Arc::new(super::Status::default()),
+    )]);
+    let app = super::router(
+        &tokio::sync::watch::channel(targets.clone()).1,
+        &None,
+        &Default::default(),
+        &Default::default(),
+        false,
+        true,
+        true,
+    );
+    let status = async || {
+        let request = http::Request::get("/status")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(
+            response.headers()[http::header::CONTENT_TYPE],
+            "application/json",
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+    };
+
+    targets[0].1.started.send_replace(super::Startup::Complete);
+    targets[0].1.set_ready(true);
+    assert_eq!(
+        status().await,
+        serde_json::json!([{
+            "name": "test",
+            "liveness": true,
+            "readiness": true,
+            "startup": "complete",
+        }]),
+    );
+
+    targets[0].1.set_ready(false);
+    assert_eq!(
+        status().await,
+        serde_json::json!([{
+            "name": "test",
+            "liveness": true,
+            "readiness": false,
+            "startup": "complete",
+        }]),
+    );
+}
+
+#[tokio::test]
+async fn test_healthz() {
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    let fixture = Fixture::new(true, true, false);
+    let targets: std::sync::Arc<[_]> = std::sync::Arc::from(
+        ["a", "b"]
+            .map(|name| {
+                (
+                    super::Target {
+                        name: name.to_string(),
+                        ..fixture.target.clone()
+                    },
+                    std::sync::Arc::new(super::Status::default()),
+                )
+            })
+            .as_slice(),
+    );
+    let app = super::router(
+        &tokio::sync::watch::channel(targets.clone()).1,
+        &None,
+        &Default::default(),
+        &Default::default(),
+        false,
+        true,
+        true,
+    );
+    let healthz = async || {
+        let request = http::Request::get("/healthz")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (
+            status,
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+
+    for (_, status) in targets.iter() {
+        status.set_ready(true);
+    }
+    assert_eq!(
+        healthz().await,
+        (http::StatusCode::OK, serde_json::json!({"failing": []})),
+    );
+
+    targets[1].1.set_live(false);
+    assert_eq!(
+        healthz().await,
+        (
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            serde_json::json!({"failing": ["b"]}),
+        ),
+    );
+}
+
+#[tokio::test]
+async fn test_admin_token() {
+    use tower::ServiceExt;
+
+    let fixture = Fixture::new(false, false, false);
+    let targets = std::sync::Arc::from([(
+        fixture.target.clone(),
+        std::sync::Arc::new(super::Status::default()),
+    )]);
+    let app = super::admin(
+        &tokio::sync::watch::channel(targets).1,
+        "token",
+        std::time::Instant::now(),
+    );
+
+    let request = http::Request::get("/debug/bundle")
+        .body(axum::body::Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+
+    let request = http::Request::get("/debug/bundle")
+        .header(http::header::AUTHORIZATION, "Bearer token")
+        .body(axum::body::Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), http::StatusCode::OK);
+}
+
+#[test]
+fn test_window() {
+    let window = serde_json::from_str::<healthzd::window::Window>(
+        r#"{"start": "22:00", "end": "06:00", "weekdays": ["fri"]}"#,
+    )
+    .unwrap();
+    let at = |s| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+    // 2026-10-16 is a Friday
+    assert!(!window.contains(at("2026-10-16 21:59")));
+    assert!(window.contains(at("2026-10-16 22:00")));
+    assert!(window.contains(at("2026-10-17 05:59")));
+    assert!(!window.contains(at("2026-10-17 06:00")));
+    assert!(!window.contains(at("2026-10-17 22:00")));
+}
+
+#[tokio::test]
+async fn test_liveness_pessimistic() {
+    use tower::ServiceExt;
+
+    let mut fixture = Fixture::new(true, false, false);
+    fixture
+        .target
+        .liveness_probe
+        .as_mut()
+        .unwrap()
+        .initial_delay = Duration::from_millis(100);
+    let targets: std::sync::Arc<[_]> = std::sync::Arc::from([(
+        fixture.target.clone(),
+        std::sync::Arc::new(super::Status::new(false)),
+    )]);
+    let app = super::router(
+        &tokio::sync::watch::channel(targets.clone()).1,
+        &None,
+        &Default::default(),
+        &Default::default(),
+        false,
+        true,
+        true,
+    );
+    let live = async || {
+        let request = http::Request::get("/live")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    };
+
+    fixture.liveness(true).await;
+    let (update, abort) = futures::future::abortable(super::update(
+        &fixture.context,
+        &targets[0].0,
+        &targets[0].1,
+        async {},
+    ));
+    let _ = futures::future::join(update, async {
+        assert_eq!(live().await, http::StatusCode::INTERNAL_SERVER_ERROR);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(live().await, http::StatusCode::OK);
+
+        abort.abort();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_push_status() {
+    let mut fixture = Fixture::new(false, true, false);
+    fixture.target.readiness_probe.as_mut().unwrap().method = probe::Method::External {
+        ttl: Duration::from_millis(300),
+        pushed: Default::default(),
+    };
+    let targets = [(
+        fixture.target.clone(),
+        std::sync::Arc::new(super::Status::default()),
+    )];
+    let push = |readiness| super::Push {
+        liveness: None,
+        readiness: Some(readiness),
+        startup: None,
+    };
+
+    assert!(super::push_status(&targets, "unknown", &push(true)).is_err());
+    let liveness = super::Push {
+        liveness: Some(true),
+        readiness: None,
+        startup: None,
+    };
+    assert!(super::push_status(&targets, "test", &liveness).is_err());
+
+    let (update, abort) = futures::future::abortable(super::update(
+        &fixture.context,
+        &targets[0].0,
+        &targets[0].1,
+        async {},
+    ));
+    let _ = futures::future::join(update, async {
+        super::push_status(&targets, "test", &push(true)).unwrap();
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        assert!(targets[0].1.ready.load(Ordering::Relaxed));
+
+        // expires without being refreshed
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        assert!(!targets[0].1.ready.load(Ordering::Relaxed));
+
+        abort.abort();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_ntp_offset() {
+    // replies as a server whose clock is 10 seconds ahead
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let port = socket.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let mut buf = [0; 48];
+            let (_, peer) = socket.recv_from(&mut buf).await.unwrap();
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                .unwrap()
+                + Duration::from_secs(10);
+            let mut timestamp = [0; 8];
+            timestamp[..4].copy_from_slice(&((now.as_secs() + 2_208_988_800) as u32).to_be_bytes());
+            let mut response = [0; 48];
+            response[0] = 0x24;
+            response[1] = 1;
+            response[24..32].copy_from_slice(&buf[40..48]);
+            response[32..40].copy_from_slice(&timestamp);
+            response[40..48].copy_from_slice(&timestamp);
+            socket.send_to(&response, peer).await.unwrap();
+        }
+    });
+
+    let context = context();
+    let probe = |max_offset| probe::Probe {
+        method: probe::Method::NtpOffset {
+            server: ("127.0.0.1".to_string(), port),
+            max_offset,
+        },
+        timeout: Duration::from_secs(1),
+        ..exec_probe(&["true"])
+    };
+
+    probe(Duration::from_secs(20)).call(&context).await.unwrap();
+    let e = probe(Duration::from_secs(5))
+        .call(&context)
+        .await
+        .unwrap_err();
+    assert!(e.to_string().starts_with("clock offset is 9."), "{e}");
+}
+
+#[tokio::test]
+async fn test_cert_file() {
+    let temp = tempfile::tempdir().unwrap();
+    let context = context();
+    let call = async |not_before, not_after, min_days| {
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        params.not_before = not_before;
+        params.not_after = not_after;
+        let key = rcgen::KeyPair::generate().unwrap();
+        let path = temp.path().join("tls.crt");
+        std::fs::write(&path, params.self_signed(&key).unwrap().pem()).unwrap();
+        probe::Probe {
+            method: probe::Method::CertFile { path, min_days },
+            ..exec_probe(&["true"])
+        }
+        .call(&context)
+        .await
+    };
+
+    call(
+        rcgen::date_time_ymd(2000, 1, 1),
+        rcgen::date_time_ymd(4000, 1, 1),
+        30,
+    )
+    .await
+    .unwrap();
+    let e = call(
+        rcgen::date_time_ymd(2000, 1, 1),
+        rcgen::date_time_ymd(2001, 1, 1),
+        0,
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        e.to_string()
+            .ends_with(" expires at Jan  1 00:00:00 2001 +00:00"),
+        "{e}"
+    );
+    // valid for now, but not for another million days
+    let e = call(
+        rcgen::date_time_ymd(2000, 1, 1),
+        rcgen::date_time_ymd(4000, 1, 1),
+        1_000_000,
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        e.to_string()
+            .ends_with(" expires at Jan  1 00:00:00 4000 +00:00"),
+        "{e}"
+    );
+    let e = call(
+        rcgen::date_time_ymd(3000, 1, 1),
+        rcgen::date_time_ymd(4000, 1, 1),
+        0,
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        e.to_string()
+            .ends_with(" is not valid before Jan  1 00:00:00 3000 +00:00"),
+        "{e}",
+    );
+}
+
+#[tokio::test]
+async fn test_nagios() {
+    let context = context();
+    let probe = |script: &str, warning_ok| {
+        let mut probe = exec_probe(&["sh", "-c", script]);
+        let probe::Method::Exec { nagios, .. } = &mut probe.method else {
+            unreachable!();
+        };
+        *nagios = Some(probe::Nagios { warning_ok });
+        probe
+    };
+    let call = async |script, warning_ok| probe(script, warning_ok).call(&context).await;
+
+    assert!(
+        call("echo 'DISK OK - free space: 72%'", false)
+            .await
+            .is_ok()
+    );
+    assert!(
+        call("echo 'DISK WARNING - free space: 9%'; exit 1", false)
+            .await
+            .is_err()
+    );
+    assert!(
+        call("echo 'DISK WARNING - free space: 9%'; exit 1", true)
+            .await
+            .is_ok()
+    );
+    // keywords count even when the script exits 0
+    assert!(call("echo CRIT", true).await.is_err());
+    assert!(call("exit 2", true).await.is_err());
+    assert!(call("echo OK; exit 3", true).await.is_err());
+}
+
+#[tokio::test]
+async fn test_exec_env() {
+    let context = context();
+    let probe = |script: &str, vars: &[(&str, &str)], clear: bool| {
+        let mut probe = exec_probe(&["/bin/sh", "-c", script]);
+        let probe::Method::Exec { env, clear_env, .. } = &mut probe.method else {
+            unreachable!();
+        };
+        *env = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        *clear_env = clear;
+        probe
+    };
+    let call =
+        async |script, env, clear_env| probe(script, env, clear_env).call(&context).await.is_ok();
+
+    assert!(!call(r#"test "$FOO" = bar"#, &[], false).await);
+    assert!(call(r#"test "$FOO" = bar"#, &[("FOO", "bar")], false).await);
+    assert!(call(r#"test -n "$HOME""#, &[], false).await);
+    assert!(!call(r#"test -n "$HOME""#, &[], true).await);
+    assert!(call(r#"test "$FOO" = bar"#, &[("FOO", "bar")], true).await);
+}
+
+#[tokio::test]
+async fn test_exec_working_dir() {
+    let context = context();
+    let temp = tempfile::tempdir().unwrap();
+    std::fs::write(temp.path().join("marker"), "").unwrap();
+    let probe = |dir: Option<PathBuf>| {
+        let mut probe = exec_probe(&["sh", "-c", "test -f ./marker"]);
+        let probe::Method::Exec { working_dir, .. } = &mut probe.method else {
+            unreachable!();
+        };
+        *working_dir = dir;
+        probe
+    };
+
+    assert!(probe(None).call(&context).await.is_err());
+    assert!(
+        probe(Some(temp.path().to_owned()))
+            .call(&context)
+            .await
+            .is_ok()
+    );
+    let e = probe(Some(temp.path().join("missing")))
+        .call(&context)
+        .await
+        .unwrap_err();
+    assert!(e.to_string().starts_with("working directory"), "{e}");
+}
+
+#[tokio::test]
+async fn test_success_exit_codes() {
+    let context = context();
+    let probe = |script: &str, codes| {
+        let mut probe = exec_probe(&["sh", "-c", script]);
+        let probe::Method::Exec {
+            success_exit_codes, ..
+        } = &mut probe.method
+        else {
+            unreachable!();
+        };
+        *success_exit_codes = codes;
+        probe
+    };
+    let call =
+        async |script, success_exit_codes| probe(script, success_exit_codes).call(&context).await;
+
+    assert!(call("exit 2", vec![0]).await.is_err());
+    assert!(call("exit 2", vec![0, 2]).await.is_ok());
+    assert!(call("exit 0", vec![0, 2]).await.is_ok());
+    assert!(call("exit 1", vec![0, 2]).await.is_err());
+    let e = call("kill -9 $$", vec![0, 2]).await.unwrap_err();
+    assert!(e.to_string().starts_with("terminated by signal"), "{e}");
+}
+
+#[tokio::test]
+async fn test_exec_output_regex() {
+    let context = context();
+    let probe = |script: &str, stdout: Option<&str>, stderr: Option<&str>| {
+        let mut probe = exec_probe(&["sh", "-c", script]);
+        let probe::Method::Exec {
+            stdout_regex,
+            stderr_regex,
+            ..
+        } = &mut probe.method
+        else {
+            unreachable!();
+        };
+        *stdout_regex = stdout.map(|regex| regex::Regex::new(regex).unwrap());
+        *stderr_regex = stderr.map(|regex| regex::Regex::new(regex).unwrap());
+        probe
+    };
+    let call = async |script, stdout_regex, stderr_regex| {
+        probe(script, stdout_regex, stderr_regex)
+            .call(&context)
+            .await
+    };
+
+    assert!(
+        call("echo status: UP", Some("(?m)^status: UP$"), None)
+            .await
+            .is_ok()
+    );
+    let e = call("echo status: DOWN", Some("(?m)^status: UP$"), None)
+        .await
+        .unwrap_err();
+    assert!(e.to_string().starts_with("stdout does not match"), "{e}");
+    assert!(call("echo ok >&2", None, Some("(?m)^ok$")).await.is_ok());
+    let e = call("echo ERROR >&2", None, Some("(?m)^ok$"))
+        .await
+        .unwrap_err();
+    assert!(e.to_string().starts_with("stderr does not match"), "{e}");
+    // the exit code is still checked
+    assert!(
+        call("echo status: UP; exit 1", Some("UP"), None)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_ready_group() {
+    use tower::ServiceExt;
+
+    let fixture = Fixture::new(false, false, false);
+    let target = |name: &str, group: &str| super::Target {
+        name: name.to_string(),
+        group: Some(group.to_string()),
+        ..fixture.target.clone()
+    };
+    let targets: std::sync::Arc<[_]> = std::sync::Arc::from([
+        (
+            target("postgres", "database"),
+            std::sync::Arc::new(super::Status::default()),
+        ),
+        (
+            target("redis", "cache"),
+            std::sync::Arc::new(super::Status::default()),
+        ),
+    ]);
+    targets[0].1.ready.store(true, Ordering::Relaxed);
+    let app = super::router(
+        &tokio::sync::watch::channel(targets.clone()).1,
+        &None,
+        &Default::default(),
+        &Default::default(),
+        false,
+        true,
+        true,
+    );
+    let ready = async |uri| {
+        let request = http::Request::get(uri)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    };
+
+    assert_eq!(ready("/ready").await, http::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(ready("/ready/group/database").await, http::StatusCode::OK);
+    assert_eq!(
+        ready("/ready/group/cache").await,
+        http::StatusCode::SERVICE_UNAVAILABLE
+    );
+    assert_eq!(
+        ready("/ready/group/unknown").await,
+        http::StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn test_follow_redirects() {
+    use axum::response::IntoResponse;
+
+    let app = axum::Router::new()
+        .route(
+            "/a/b",
+            axum::routing::get(async || axum::response::Redirect::temporary("c")),
+        )
+        .route("/a/c", axum::routing::get(async || "ok"));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = app
+        .route(
+            "/a/query",
+            axum::routing::get(async |axum::extract::RawQuery(query)| match query {
+                Some(query) if query == "x=1" => "ok".into_response(),
+                _ => axum::response::Redirect::temporary("?x=1").into_response(),
+            }),
+        )
+        .route(
+            "/a/scheme-relative",
+            axum::routing::get(async move || {
+                axum::response::Redirect::temporary(&format!("//{addr}/a/c"))
+            }),
+        );
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let probe = |path: &str, follow_redirects| {
+        let mut probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {
+                "host": addr.ip().to_string(),
+                "port": addr.port(),
+                "path": path,
+                "follow_redirects": follow_redirects,
+            },
+        }))
+        .unwrap();
+        probe.timeout = Duration::from_secs(1);
+        probe
+    };
+
+    assert!(probe("/a/b", false).call(&context).await.is_err());
+    probe("/a/b", true).call(&context).await.unwrap();
+    probe("/a/query", true).call(&context).await.unwrap();
+    probe("/a/scheme-relative", true)
+        .call(&context)
+        .await
+        .unwrap();
+
+    // from https back to the plain http server above
+    let certified = rcgen::generate_simple_self_signed(["localhost".to_string()]).unwrap();
+    let temp = tempfile::tempdir().unwrap();
+    let (cert, key) = write_pem(
+        temp.path(),
+        "server",
+        &certified.cert,
+        &certified.signing_key,
+    );
+    let location = format!("http://{addr}/a/c");
+    let port = serve_tls(
+        hyper::server_tls_config(&cert, &key).unwrap(),
+        axum::Router::new().route(
+            "/",
+            axum::routing::get(async move || axum::response::Redirect::temporary(&location)),
+        ),
+    )
+    .await;
+    let downgrade = async |allow_downgrade_redirect: bool| {
+        let mut probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {
+                "scheme": "HTTPS",
+                "port": port,
+                "insecure_skip_tls_verify": true,
+                "follow_redirects": true,
+                "allow_downgrade_redirect": allow_downgrade_redirect,
+            },
+        }))
+        .unwrap();
+        probe.timeout = Duration::from_secs(1);
+        probe.call(&context).await
+    };
+
+    let e = downgrade(false).await.unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        format!("redirect from https://localhost:{port}/ downgrades to http://{addr}/a/c"),
+    );
+    downgrade(true).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_cold_checks() {
+    let mut fixture = Fixture::new(true, false, false);
+    fixture.target.liveness_probe.as_mut().unwrap().cold_checks = 2;
+    let (update, abort) = futures::future::abortable(fixture.update());
+    let _ = futures::future::join(update, async {
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        assert!(fixture.status.live.load(Ordering::Relaxed));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(!fixture.status.live.load(Ordering::Relaxed));
+
+        abort.abort();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_retry_on_codes() {
+    // fails twice with the given status before succeeding
+    let flaky = |status| {
+        let count = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        axum::routing::get(async move || {
+            if count.fetch_add(1, Ordering::Relaxed) < 2 {
+                status
+            } else {
+                http::StatusCode::OK
+            }
+        })
+    };
+    let app = axum::Router::new()
+        .route("/502", flaky(http::StatusCode::BAD_GATEWAY))
+        .route("/500", flaky(http::StatusCode::INTERNAL_SERVER_ERROR));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let probe = |path| {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {
+                "host": addr.ip().to_string(),
+                "port": addr.port(),
+                "path": path,
+                "retry_on_codes": [502],
+            },
+            "retries": 2,
+        }))
+        .unwrap()
+    };
+
+    probe("/502").call(&context).await.unwrap();
+    let e = probe("/500").call(&context).await.unwrap_err();
+    assert_eq!(e.to_string(), "500 Internal Server Error");
+}
+
+#[tokio::test]
+async fn test_once() {
+    let fixture = Fixture::new(true, true, false);
+    let targets = [(
+        fixture.target.clone(),
+        std::sync::Arc::new(super::Status::default()),
+    )];
+
+    fixture.liveness(true).await;
+    fixture.readiness(true).await;
+    assert_eq!(super::once(&fixture.context, &targets).await, 0);
+
+    fixture.readiness(false).await;
+    assert_eq!(super::once(&fixture.context, &targets).await, 11);
+}
+
+#[test]
+fn test_bench_summary() {
+    let durations = (1..=100).rev().map(Duration::from_millis).collect();
+    assert_eq!(
+        super::summary(durations, 75),
+        "count: 100\nsuccess: 75.0%\nmin: 1ms\np50: 50ms\np95: 95ms\nmax: 100ms\n",
+    );
+    assert_eq!(super::summary(Vec::new(), 0), "");
+}
+
+#[tokio::test]
+async fn test_validator_command() {
+    let app = axum::Router::new().route("/", axum::routing::get(async || "status: green"));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let probe = |pattern| {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {
+                "host": addr.ip().to_string(),
+                "port": addr.port(),
+                "validator_command": ["grep", "-q", pattern],
+            },
+        }))
+        .unwrap()
+    };
+
+    probe("^HTTP/1.1 200 OK").call(&context).await.unwrap();
+    probe("^status: green$").call(&context).await.unwrap();
+    let e = probe("red").call(&context).await.unwrap_err();
+    assert_eq!(e.to_string(), "validator exit status: 1");
+}
+
+#[tokio::test]
+async fn test_startup_phase() {
+    let fixture = Fixture::new(false, false, true);
+    let targets = [
+        (
+            fixture.target.clone(),
+            std::sync::Arc::new(super::Status::default()),
+        ),
+        (
+            super::Target {
+                name: "later".to_string(),
+                startup_probe: None,
+                startup_phase: 1,
+                ..fixture.target.clone()
+            },
+            std::sync::Arc::new(super::Status::default()),
+        ),
+    ];
+    let (update, abort) = futures::future::abortable(futures::future::join_all(
+        targets.iter().map(|(target, status)| {
+            super::update(
+                &fixture.context,
+                target,
+                status,
+                super::started_before(&targets, target.startup_phase),
+            )
+        }),
+    ));
+    let _ = futures::future::join(update, async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(!targets[1].1.ready.load(Ordering::Relaxed));
+
+        fixture.startup(true).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(targets[1].1.ready.load(Ordering::Relaxed));
+
+        abort.abort();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_retry_stderr_regex() {
+    let temp = tempfile::tempdir().unwrap();
+    let context = context();
+    // fails with the given message until the second attempt
+    let probe = |name: &str, stderr: &str| {
+        let path = temp.path().join(name);
+        let script = format!(
+            "test -f {0} && exit 0; touch {0}; echo '{stderr}' >&2; exit 1",
+            path.display(),
+        );
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "exec": {
+                "command": ["sh", "-c", script],
+                "retry_stderr_regex": "connection reset",
+            },
+            "retries": 1,
+        }))
+        .unwrap()
+    };
+
+    probe("reset", "read: connection reset by peer")
+        .call(&context)
+        .await
+        .unwrap();
+    let e = probe("refused", "connection refused")
+        .call(&context)
+        .await
+        .unwrap_err();
+    assert_eq!(e.to_string(), "exit status: 1: connection refused");
+
+    // nagios plugins report through their exit code
+    let e = serde_json::from_value::<probe::Probe>(serde_json::json!({
+        "exec": {
+            "command": ["true"],
+            "nagios": {},
+            "retry_stderr_regex": "connection reset",
+        },
+    }))
+    .unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "retry_stderr_regex cannot be used with nagios"
+    );
+}
+
+#[tokio::test]
+async fn test_present() {
+    let temp = tempfile::tempdir().unwrap();
+    let context = context();
+    let probe = |method| probe::Probe {
+        method,
+        ..exec_probe(&["true"])
+    };
+    let env = |name: &str| {
+        probe(probe::Method::EnvPresent {
+            name: name.to_string(),
+        })
+    };
+    let secret = |name: &str| {
+        probe(probe::Method::SecretPresent {
+            path: temp.path().join(name),
+        })
+    };
+
+    env("PATH").call(&context).await.unwrap();
+    assert!(env("HEALTHZD_TEST_UNSET").call(&context).await.is_err());
+
+    tokio::fs::write(temp.path().join("token"), b"secret")
+        .await
+        .unwrap();
+    tokio::fs::write(temp.path().join("empty"), b"")
+        .await
+        .unwrap();
+    secret("token").call(&context).await.unwrap();
+    assert!(secret("empty").call(&context).await.is_err());
+    assert!(secret("missing").call(&context).await.is_err());
+}
+
+#[tokio::test]
+async fn test_text() {
+    let temp = tempfile::tempdir().unwrap();
+    let path = temp.path().join("password");
+    std::fs::write(&path, "secret\n").unwrap();
+    let render = async |s: &str| probe::Text::parse(s)?.render().await;
+
+    assert_eq!(
+        render("path={{ env:PATH }}").await.unwrap(),
+        format!("path={}", std::env::var("PATH").unwrap()),
+    );
+    // trailing newlines are left out
+    assert_eq!(
+        render(&format!("user:{{{{file:{}}}}}!", path.display()))
+            .await
+            .unwrap(),
+        "user:secret!",
+    );
+    assert_eq!(render("no placeholders").await.unwrap(), "no placeholders");
+
+    let e = render("{{env:PATH").await.unwrap_err();
+    assert_eq!(e.to_string(), "unterminated placeholder");
+    let e = render("{{vault:secret}}").await.unwrap_err();
+    assert_eq!(e.to_string(), r#"unknown placeholder "vault:secret""#);
+    let e = render("{{env:HEALTHZD_TEST_UNSET}}").await.unwrap_err();
+    assert_eq!(
+        format!("{e:#}"),
+        "failed to read environment variable HEALTHZD_TEST_UNSET: environment variable not found",
+    );
+    let e = render(&format!(
+        "{{{{file:{}}}}}",
+        temp.path().join("missing").display()
+    ))
+    .await
+    .unwrap_err();
+    assert!(e.to_string().starts_with("failed to read "), "{e}");
+}
+
+#[tokio::test]
+async fn test_result_window() {
+    let mut fixture = Fixture::new(false, true, false);
+    fixture.target.readiness_probe.as_mut().unwrap().window = Some(probe::ResultWindow {
+        size: 4,
+        failures: 2,
+        successes: 3,
+    });
+    fixture.readiness(true).await;
+    let (update, abort) = futures::future::abortable(fixture.update());
+    let _ = futures::future::join(update, async {
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        assert!(!fixture.status.ready.load(Ordering::Relaxed));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(fixture.status.ready.load(Ordering::Relaxed));
+
+        fixture.readiness(false).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // a single failure is tolerated
+        assert!(fixture.status.ready.load(Ordering::Relaxed));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(!fixture.status.ready.load(Ordering::Relaxed));
+
+        abort.abort();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_connect_timeout() {
+    let app = axum::Router::new().route(
+        "/",
+        axum::routing::get(async || {
+            tokio::time::sleep(Duration::from_millis(1500)).await;
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let probe = |connect_timeout_seconds: Option<u64>| {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {
+                "host": addr.ip().to_string(),
+                "port": addr.port(),
+                "connect_timeout_seconds": connect_timeout_seconds,
+            },
+            "timeout_seconds": 1,
+        }))
+        .unwrap()
+    };
+
+    // the connect timeout is on top of the timeout
+    let e = probe(None).call(&context).await.unwrap_err();
+    assert!(e.is::<tokio::time::error::Elapsed>());
+    probe(Some(1)).call(&context).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_write_state() {
+    let fixture = Fixture::new(false, false, false);
+    let targets = [(
+        fixture.target.clone(),
+        std::sync::Arc::new(super::Status::default()),
+    )];
+    let path = fixture._temp.path().join("state.json");
+    let read = async || {
+        serde_json::from_slice::<serde_json::Value>(&tokio::fs::read(&path).await.unwrap()).unwrap()
+    };
+
+    let (write, abort) = futures::future::abortable(super::write_state(
+        &path,
+        tokio::sync::watch::channel(super::Targets::from(targets.clone())).1,
+    ));
+    let _ = futures::future::join(write, async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(
+            read().await,
+            serde_json::json!([{"name": "test", "live": true, "ready": false}]),
+        );
+
+        targets[0].1.set_ready(true);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(
+            read().await,
+            serde_json::json!([{"name": "test", "live": true, "ready": true}]),
+        );
+
+        abort.abort();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_tcp_exchange() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // answers PING with +PONG like Redis, and anything else with an error
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 6];
+            stream.read_exact(&mut buf).await.unwrap();
+            let response: &[u8] = if &buf == b"PING\r\n" {
+                b"+PONG\r\n"
+            } else {
+                b"-ERR\r\n"
+            };
+            stream.write_all(response).await.unwrap();
+        }
+    });
+
+    let context = context();
+    let probe = |method: serde_json::Value| {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({ "tcp_exchange": method }))
+            .unwrap()
+    };
+
+    probe(serde_json::json!({"port": port, "send": "PING\r\n", "expect_prefix": "+PONG"}))
+        .call(&context)
+        .await
+        .unwrap();
+    probe(
+        serde_json::json!({"port": port, "send_hex": "50494e470d0a", "expect_regex": "^\\+P.NG"}),
+    )
+    .call(&context)
+    .await
+    .unwrap();
+    let e = probe(serde_json::json!({"port": port, "send": "QUIT\r\n", "expect_prefix": "+PONG"}))
+        .call(&context)
+        .await
+        .unwrap_err();
+    assert_eq!(e.to_string(), r#"unexpected response "-ERR\r\n""#);
+}
+
+#[test]
+fn test_cidr() {
+    let cidr = |s: &str| s.parse::<crate::cidr::Cidr>().unwrap();
+    let ip = |s: &str| s.parse::<std::net::IpAddr>().unwrap();
+
+    assert!(cidr("10.0.0.0/8").contains(ip("10.1.2.3")));
+    assert!(!cidr("10.0.0.0/8").contains(ip("11.0.0.0")));
+    assert!(cidr("127.0.0.1").contains(ip("127.0.0.1")));
+    assert!(!cidr("127.0.0.1").contains(ip("127.0.0.2")));
+    assert!(cidr("0.0.0.0/0").contains(ip("192.0.2.1")));
+    // IPv4-mapped addresses as accepted on a dual-stack socket
+    assert!(cidr("127.0.0.0/8").contains(ip("::ffff:127.0.0.1")));
+    assert!(cidr("fd00::/8").contains(ip("fd12::1")));
+    assert!(!cidr("fd00::/8").contains(ip("127.0.0.1")));
+    assert!("10.0.0.0/33".parse::<crate::cidr::Cidr>().is_err());
+}
+
+#[tokio::test]
+async fn test_uris() {
+    let counts: std::sync::Arc<[std::sync::atomic::AtomicUsize; 2]> = Default::default();
+    let route = |i: usize| {
+        let counts = counts.clone();
+        axum::routing::get(async move || {
+            counts[i].fetch_add(1, Ordering::Relaxed);
+        })
+    };
+    let app = axum::Router::new()
+        .route("/a", route(0))
+        .route("/b", route(1));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let probe = |selection, weight| {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {
+                "uris": [
+                    format!("http://{addr}/a"),
+                    {"uri": format!("http://{addr}/b"), "weight": weight},
+                ],
+                "selection": selection,
+            },
+        }))
+        .unwrap()
+    };
+    let take = || {
+        counts
+            .each_ref()
+            .map(|count| count.swap(0, Ordering::Relaxed))
+    };
+
+    let round_robin = probe("round_robin", 10000);
+    for round in 0..4 {
+        round_robin.call_round(&context, round).await.unwrap();
+    }
+    assert_eq!(take(), [2, 2]);
+
+    let weighted_random = probe("weighted_random", 10000);
+    for round in 0..20 {
+        weighted_random.call_round(&context, round).await.unwrap();
+    }
+    assert!(take()[1] >= 18);
+
+    // the weights add up past u32::MAX
+    let weighted_random = probe("weighted_random", u32::MAX);
+    for round in 0..20 {
+        weighted_random.call_round(&context, round).await.unwrap();
+    }
+    assert!(take()[1] >= 18);
+}
+
+#[tokio::test]
+async fn test_redis() {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+
+    // a primary requiring a password whose db 1 is still loading
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut stream = tokio::io::BufReader::new(stream);
+                let mut db = 0;
+                loop {
+                    let mut line = String::new();
+                    if stream.read_line(&mut line).await.unwrap() == 0 {
+                        break;
+                    }
+                    let mut args = Vec::new();
+                    for _ in 0..line[1..].trim().parse().unwrap() {
+                        let mut line = String::new();
+                        stream.read_line(&mut line).await.unwrap();
+                        let mut arg = vec![0; line[1..].trim().parse::<usize>().unwrap() + 2];
+                        stream.read_exact(&mut arg).await.unwrap();
+                        arg.truncate(arg.len() - 2);
+                        args.push(String::from_utf8(arg).unwrap());
+                    }
+                    let response: &[u8] = match args[0].as_str() {
+                        "AUTH" if args[1] == "secret" => b"+OK\r\n",
+                        "AUTH" => b"-WRONGPASS invalid password\r\n",
+                        "SELECT" => {
+                            db = args[1].parse().unwrap();
+                            b"+OK\r\n"
+                        }
+                        "PING" if db == 1 => b"-LOADING loading the dataset\r\n",
+                        "PING" => b"+PONG\r\n",
+                        "ROLE" => b"*3\r\n$6\r\nmaster\r\n:0\r\n*0\r\n",
+                        _ => b"-ERR unknown command\r\n",
+                    };
+                    stream.get_mut().write_all(response).await.unwrap();
+                }
+            });
+        }
+    });
+
+    let context = context();
+    let call = async |method: serde_json::Value| {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({ "redis": method }))
+            .unwrap()
+            .call(&context)
+            .await
+    };
+
+    call(serde_json::json!({"port": port, "password": "secret", "expect_role": "master"}))
+        .await
+        .unwrap();
+    let e = call(serde_json::json!({"port": port, "password": "wrong"}))
+        .await
+        .unwrap_err();
+    assert_eq!(e.to_string(), "WRONGPASS invalid password");
+    let e = call(serde_json::json!({"port": port, "password": "secret", "db": 1}))
+        .await
+        .unwrap_err();
+    assert_eq!(e.to_string(), "LOADING loading the dataset");
+    let e = call(serde_json::json!({"port": port, "password": "secret", "expect_role": "slave"}))
+        .await
+        .unwrap_err();
+    assert_eq!(e.to_string(), "role is Master, expected Replica");
+}
+
+#[test]
+fn test_result_ttl() {
+    let probe = |policy: Option<&str>| {
+        let mut probe = serde_json::json!({
+            "exec": {"command": ["true"]},
+            "result_ttl_seconds": 1,
+        });
+        if let Some(policy) = policy {
+            probe["result_ttl_policy"] = policy.into();
+        }
+        serde_json::from_value::<probe::Probe>(probe).unwrap()
+    };
+    let report = probe::Report::default();
+    let at = |elapsed| {
+        *report.last_result.lock().unwrap() = Some(tokio::time::Instant::now() - elapsed);
+    };
+
+    // no verdict before the probe is started
+    assert_eq!(probe(None).stale(&report), None);
+    // nor before its first result is overdue, but one that never arrives goes stale too
+    *report.watching_since.lock().unwrap() = Some(tokio::time::Instant::now());
+    assert_eq!(probe(None).stale(&report), None);
+    *report.watching_since.lock().unwrap() =
+        Some(tokio::time::Instant::now() - Duration::from_secs(2));
+    assert_eq!(probe(None).stale(&report), Some(false));
+    let delayed = probe::Probe {
+        initial_delay: Duration::from_secs(5),
+        ..probe(None)
+    };
+    assert_eq!(delayed.stale(&report), None);
+    at(Duration::ZERO);
+    assert_eq!(probe(None).stale(&report), None);
+    at(Duration::from_secs(2));
+    assert_eq!(probe(None).stale(&report), Some(false));
+    assert_eq!(probe(Some("fail_closed")).stale(&report), Some(false));
+    assert_eq!(probe(Some("fail_open")).stale(&report), Some(true));
+
+    assert!(
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "exec": {"command": ["true"]},
+            "result_ttl_policy": "fail_open",
+        }))
+        .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_uris_error() {
+    let app = axum::Router::new().route("/a", axum::routing::get(async || {}));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+        "http_get": {"uris": [format!("http://{addr}/a"), format!("http://{addr}/b")]},
+    }))
+    .unwrap();
+
+    probe.call_round(&context, 0).await.unwrap();
+    let e = probe.call_round(&context, 1).await.unwrap_err();
+    assert_eq!(
+        format!("{e:#}"),
+        format!("uris[1] http://{addr}/b: 404 Not Found"),
+    );
+    assert!(e.is::<probe::UnexpectedStatus>());
+}
+
+#[tokio::test]
+async fn test_draining() {
+    use futures::FutureExt;
+    use tower::ServiceExt;
+
+    let targets: std::sync::Arc<[_]> = std::sync::Arc::from([(
+        serde_json::from_value::<super::Target>(serde_json::json!({"name": "test"})).unwrap(),
+        std::sync::Arc::new(super::Status::default()),
+    )]);
+    targets[0].1.set_ready(true);
+    let draining = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let app = super::router(
+        &tokio::sync::watch::channel(targets.clone()).1,
+        &None,
+        &draining,
+        &Default::default(),
+        false,
+        true,
+        true,
+    );
+    let ready = async || {
+        let request = http::Request::get("/ready")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    };
+
+    assert_eq!(ready().await, http::StatusCode::OK);
+    draining.store(true, Ordering::Relaxed);
+    assert_eq!(ready().await, http::StatusCode::SERVICE_UNAVAILABLE);
+
+    // outlives a future that completes early
+    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+    let until = tokio::spawn(super::until(rx.map(drop), async {}));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!until.is_finished());
+    tx.send(()).unwrap();
+    until.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_hooks() {
+    let mut fixture = Fixture::new(false, true, false);
+    let temp = tempfile::tempdir().unwrap();
+    let log = temp.path().join("hooks");
+    let hook = |label: &str| {
+        Some((
+            "sh".to_string(),
+            vec![
+                "-c".to_string(),
+                format!(
+                    "echo {label} $HEALTHZD_TARGET $HEALTHZD_PROBE $HEALTHZD_STATE >> {}",
+                    log.display(),
+                ),
+            ],
+        ))
+    };
+    let probe = fixture.target.readiness_probe.as_mut().unwrap();
+    probe.on_success = hook("up");
+    probe.on_failure = hook("down");
+
+    let (update, abort) = futures::future::abortable(fixture.update());
+    let _ = futures::future::join(update, async {
+        // the initial failure is a transition from the unknown state
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        fixture.readiness(true).await;
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        fixture.readiness(false).await;
+        tokio::time::sleep(Duration::from_millis(250)).await;
+
+        assert_eq!(
+            tokio::fs::read_to_string(&log).await.unwrap(),
+            "down test readiness failure\nup test readiness success\ndown test readiness failure\n",
+        );
+
+        abort.abort();
+    })
+    .await;
+}
+
+#[test]
+fn test_privilege_resolve() {
+    use nix::unistd::{Gid, Uid};
+
+    let resolve = |user, group| crate::privilege::resolve(user, group).unwrap();
+    assert_eq!(resolve(None, None), (None, None));
+    assert_eq!(
+        resolve(Some("root"), None),
+        (Some(Uid::from_raw(0)), Some(Gid::from_raw(0))),
+    );
+    assert_eq!(
+        resolve(Some("0"), Some("12345")),
+        (Some(Uid::from_raw(0)), Some(Gid::from_raw(12345))),
+    );
+    assert_eq!(
+        resolve(None, Some("12345")),
+        (None, Some(Gid::from_raw(12345)))
+    );
+    assert!(crate::privilege::resolve(Some("no-such-user"), None).is_err());
+    // no passwd entry to take the primary group from
+    assert!(crate::privilege::resolve(Some("54321"), None).is_err());
+}
+
+#[tokio::test]
+async fn test_postgres() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // https://www.postgresql.org/docs/current/protocol-flow.html
+    async fn serve(starting_up: bool) -> u16 {
+        fn message(tag: u8, body: &[u8]) -> Vec<u8> {
+            let mut message = vec![tag];
+            message.extend_from_slice(&(body.len() as u32 + 4).to_be_bytes());
+            message.extend_from_slice(body);
+            message
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                // SSLRequest, declined
+                let mut request = [0; 8];
+                stream.read_exact(&mut request).await.unwrap();
+                stream.write_all(b"N").await.unwrap();
+                let len = stream.read_u32().await.unwrap();
+                let mut startup = vec![0; len as usize - 4];
+                stream.read_exact(&mut startup).await.unwrap();
+                if starting_up {
+                    let error = message(
+                        b'E',
+                        b"SFATAL\0VFATAL\0C57P03\0Mthe database system is starting up\0\0",
+                    );
+                    stream.write_all(&error).await.unwrap();
+                    continue;
+                }
+                stream
+                    .write_all(&[message(b'R', &[0; 4]), message(b'Z', b"I")].concat())
+                    .await
+                    .unwrap();
+                let tag = stream.read_u8().await.unwrap();
+                assert_eq!(tag, b'Q');
+                let len = stream.read_u32().await.unwrap();
+                let mut query = vec![0; len as usize - 4];
+                stream.read_exact(&mut query).await.unwrap();
+                assert_eq!(query, b"SELECT 1\0");
+                stream
+                    .write_all(&[message(b'C', b"SELECT 1\0"), message(b'Z', b"I")].concat())
+                    .await
+                    .unwrap();
+                // until Terminate, as the client treats an early close as an error
+                stream.read_to_end(&mut Vec::new()).await.unwrap();
+            }
+        });
+        port
+    }
+
+    let context = context();
+    let call = async |port| {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "postgres": {
+                "connection_string": format!("host=127.0.0.1 port={port} user=healthzd"),
+            },
+        }))
+        .unwrap()
+        .call(&context)
+        .await
+    };
+
+    call(serve(false).await).await.unwrap();
+    let e = call(serve(true).await).await.unwrap_err();
+    assert!(
+        format!("{e:#}").contains("the database system is starting up"),
+        "{e:#}",
+    );
+}
+
+#[tokio::test]
+async fn test_require_startup() {
+    let mut fixture = Fixture::new(false, true, true);
+    fixture
+        .target
+        .readiness_probe
+        .as_mut()
+        .unwrap()
+        .require_startup = true;
+
+    // regardless of the readiness probe itself
+    fixture.status.set_ready(true);
+    assert!(!super::is_ready(&fixture.target, &fixture.status));
+    fixture.status.set_ready(false);
+
+    let (update, abort) = futures::future::abortable(fixture.update());
+    let _ = futures::future::join(update, async {
+        fixture.readiness(true).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(!super::is_ready(&fixture.target, &fixture.status));
+
+        fixture.startup(true).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(super::is_ready(&fixture.target, &fixture.status));
+
+        abort.abort();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_statsd() {
+    use crate::statsd;
+
+    let collector = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    collector
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let addr = collector.local_addr().unwrap().to_string();
+    let receive = || {
+        let mut buf = [0; 512];
+        let len = collector.recv(&mut buf).unwrap();
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    };
+
+    let sink =
+        std::sync::Arc::new(statsd::Statsd::connect(&addr, statsd::Format::Dogstatsd).unwrap())
+            .sink(&[("target", "db.primary"), ("probe", "readiness")]);
+    sink.count("probe.result", &[("result", "success")]);
+    assert_eq!(
+        receive(),
+        "healthzd.probe.result:1|c|#target:db_primary,probe:readiness,result:success",
+    );
+    sink.timing("probe.duration", Duration::from_millis(5));
+    assert_eq!(
+        receive(),
+        "healthzd.probe.duration:5|ms|#target:db_primary,probe:readiness",
+    );
+
+    let sink = std::sync::Arc::new(statsd::Statsd::connect(&addr, statsd::Format::Statsd).unwrap())
+        .sink(&[("target", "db.primary"), ("probe", "readiness")]);
+    sink.count("probe.result", &[("result", "failure")]);
+    assert_eq!(
+        receive(),
+        "healthzd.db_primary.readiness.failure.probe.result:1|c",
+    );
+
+    // nothing listening, which must not fail the caller
+    drop(collector);
+    sink.count("probe.result", &[("result", "failure")]);
+    sink.count("probe.result", &[("result", "failure")]);
+}
+
+#[tokio::test]
+async fn test_redirect_cookies() {
+    let app = axum::Router::new()
+        .route(
+            "/login",
+            axum::routing::get(async || {
+                (
+                    [(http::header::SET_COOKIE, "session=abc; Path=/; HttpOnly")],
+                    axum::response::Redirect::temporary("/home"),
+                )
+            }),
+        )
+        .route(
+            "/home",
+            axum::routing::get(async |headers: http::HeaderMap| {
+                if headers
+                    .get(http::header::COOKIE)
+                    .is_some_and(|cookie| cookie == "session=abc")
+                {
+                    http::StatusCode::OK
+                } else {
+                    http::StatusCode::UNAUTHORIZED
+                }
+            }),
+        )
+        .route(
+            "/loop",
+            axum::routing::get(async || axum::response::Redirect::temporary("/loop")),
+        )
+        .route(
+            "/sticky",
+            axum::routing::get(async || {
+                (
+                    [(http::header::SET_COOKIE, "session=abc")],
+                    axum::response::Redirect::temporary("/sticky"),
+                )
+            }),
+        )
+        .route(
+            "/gate",
+            axum::routing::get(async |headers: http::HeaderMap| {
+                if headers.contains_key(http::header::COOKIE) {
+                    axum::response::IntoResponse::into_response(http::StatusCode::OK)
+                } else {
+                    axum::response::IntoResponse::into_response((
+                        [(http::header::SET_COOKIE, "session=abc")],
+                        axum::response::Redirect::temporary("/gate"),
+                    ))
+                }
+            }),
+        )
+        .route(
+            "/chain/{n}",
+            axum::routing::get(async |axum::extract::Path(n): axum::extract::Path<u32>| {
+                axum::response::Redirect::temporary(&format!("/chain/{}", n + 1))
+            }),
+        )
+        .route(
+            "/nowhere",
+            axum::routing::get(async || http::StatusCode::FOUND),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let call = async |path: &str, options: serde_json::Value| {
+        let mut method = serde_json::json!({
+            "host": addr.ip().to_string(),
+            "port": addr.port(),
+            "path": path,
+            "follow_redirects": true,
+        });
+        method
+            .as_object_mut()
+            .unwrap()
+            .extend(options.as_object().unwrap().clone());
+        let mut probe =
+            serde_json::from_value::<probe::Probe>(serde_json::json!({ "http_get": method }))
+                .unwrap();
+        probe.timeout = Duration::from_secs(1);
+        probe.call(&context).await
+    };
+
+    let e = call("/login", serde_json::json!({})).await.unwrap_err();
+    assert_eq!(e.to_string(), "401 Unauthorized");
+    call("/login", serde_json::json!({"redirect_cookies": true}))
+        .await
+        .unwrap();
+    let e = call("/chain/0", serde_json::json!({"max_redirects": 2}))
+        .await
+        .unwrap_err();
+    assert_eq!(e.to_string(), "too many redirects");
+    let e = call("/loop", serde_json::json!({})).await.unwrap_err();
+    assert!(e.to_string().starts_with("redirect loop at "), "{e}");
+    // back to the same URI with a new cookie
+    call("/gate", serde_json::json!({"redirect_cookies": true}))
+        .await
+        .unwrap();
+    let e = call("/sticky", serde_json::json!({"redirect_cookies": true}))
+        .await
+        .unwrap_err();
+    assert!(e.to_string().starts_with("redirect loop at "), "{e}");
+    let e = call("/nowhere", serde_json::json!({})).await.unwrap_err();
+    assert_eq!(e.to_string(), "redirect without a location");
+}
+
+#[tokio::test]
+async fn test_fd() {
+    let context = context();
+    let call = async |method: serde_json::Value| {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({ "fd": method }))
+            .unwrap()
+            .call(&context)
+            .await
+    };
+
+    call(serde_json::json!({"max_open": 1 << 20}))
+        .await
+        .unwrap();
+    let e = call(serde_json::json!({"max_open": 0})).await.unwrap_err();
+    assert!(e.to_string().ends_with("open files exceed 0"), "{e}");
+    call(serde_json::json!({"scope": "system", "max_percent": 100.}))
+        .await
+        .unwrap();
+    assert!(
+        serde_json::from_value::<probe::Probe>(serde_json::json!({"fd": {"scope": "system"}}))
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_versioned() {
+    use tower::ServiceExt;
+
+    let targets: std::sync::Arc<[_]> = std::sync::Arc::from([]);
+    let router = || {
+        super::router(
+            &tokio::sync::watch::channel(targets.clone()).1,
+            &None,
+            &Default::default(),
+            &Default::default(),
+            false,
+            true,
+            true,
+        )
+    };
+    let headers = async |app: axum::Router| {
+        let request = http::Request::get("/live")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap().headers().clone()
+    };
+
+    let response = headers(super::versioned(router(), None)).await;
+    assert_eq!(response["x-healthzd-version"], env!("CARGO_PKG_VERSION"));
+    assert!(!response.contains_key("x-app-version"));
+    let response = headers(super::versioned(
+        router(),
+        Some(http::HeaderValue::from_static("1.2.3")),
+    ))
+    .await;
+    assert_eq!(response["x-app-version"], "1.2.3");
+}
+
+#[tokio::test]
+async fn test_connect_order() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let resolver = hyper::Resolver::new(None);
+
+    for connect_order in [
+        hyper::ConnectOrder::HappyEyeballs,
+        hyper::ConnectOrder::Ordered,
+    ] {
+        let stream = hyper::connect(&resolver, "localhost", port, connect_order)
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), listener.local_addr().unwrap());
+        assert!(
+            hyper::connect(&resolver, "127.0.0.1", 1, connect_order)
+                .await
+                .is_err()
+        );
+    }
+}
+
+#[test]
+fn test_period_jitter() {
+    let mut rng = <rand::rngs::StdRng as rand::SeedableRng>::seed_from_u64(0);
+    let period = Duration::from_secs(10);
+    let periods = (0..20)
+        .map(|_| probe::jittered(period, 0.2, &mut rng))
+        .collect::<Vec<_>>();
+    assert!(
+        periods
+            .iter()
+            .all(|p| (Duration::from_secs(8)..=Duration::from_secs(12)).contains(p)),
+        "{periods:?}",
+    );
+    assert!(periods.iter().any(|p| *p != periods[0]), "{periods:?}");
+
+    assert_eq!(probe::jittered(period, 0.0, &mut rng), period);
+    assert!((0..100).all(|_| !probe::jittered(period, 1.0, &mut rng).is_zero()));
+    assert!(
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "exec": {"command": ["true"]},
+            "period_jitter": 1.5,
+        }))
+        .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_backoff() {
+    use futures::StreamExt;
+
+    let healthy = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let attempts = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let app = axum::Router::new().route(
+        "/",
+        axum::routing::get({
+            let healthy = healthy.clone();
+            let attempts = attempts.clone();
+            async move || {
+                attempts.lock().unwrap().push(tokio::time::Instant::now());
+                if healthy.load(Ordering::Relaxed) {
+                    http::StatusCode::OK
+                } else {
+                    http::StatusCode::SERVICE_UNAVAILABLE
+                }
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let mut probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+        "http_get": {"host": addr.ip().to_string(), "port": addr.port()},
+        "failure_threshold": 1000,
+        "backoff_multiplier": 2.0,
+        "max_backoff_seconds": 1,
+    }))
+    .unwrap();
+    // sub-second periods can only be set directly
+    probe.period = Duration::from_millis(50);
+    probe.backoff = Some((2.0, Duration::from_millis(400)));
+    let report = probe::Report::default();
+    let gaps = || {
+        attempts
+            .lock()
+            .unwrap()
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect::<Vec<_>>()
+    };
+
+    let watch = probe.watch(&context, &report).for_each(async |_| {});
+    tokio::select! {
+        _ = watch => unreachable!(),
+        _ = async {
+            tokio::time::sleep(Duration::from_millis(1500)).await;
+            // 100ms, 200ms, 400ms, then capped
+            let down = gaps();
+            assert!(down[0] < Duration::from_millis(180), "{down:?}");
+            assert!(down[1] > Duration::from_millis(180), "{down:?}");
+            assert!(down[2] > Duration::from_millis(380), "{down:?}");
+            assert!(
+                down[3..].iter().all(|gap| *gap < Duration::from_millis(480)),
+                "{down:?}",
+            );
+
+            healthy.store(true, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(700)).await;
+            let up = gaps().split_off(down.len() + 1);
+            assert!(up.len() >= 3, "{up:?}");
+            assert!(
+                up.iter().all(|gap| *gap < Duration::from_millis(100)),
+                "{up:?}",
+            );
+        } => {}
+    }
+}
+
+#[tokio::test]
+async fn test_duration_histogram() {
+    use futures::StreamExt;
+
+    let context = context();
+    let probe = |script: &str, timeout| probe::Probe {
+        timeout,
+        period: Duration::from_secs(10),
+        ..exec_probe(&["sh", "-c", script])
+    };
+    let observe = async |probe: probe::Probe| {
+        let report = probe::Report::default();
+        let _ = tokio::time::timeout(
+            Duration::from_secs(1),
+            probe.watch(&context, &report).for_each(async |_| {}),
+        )
+        .await;
+        let count = report.durations.count.load(Ordering::Relaxed);
+        let buckets = report
+            .durations
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        let sum = *report.durations.sum.lock().unwrap();
+        (count, buckets, sum)
+    };
+    // the index of the bucket with the bound of `seconds`
+    let bucket = |seconds| {
+        probe::DURATION_BUCKETS
+            .iter()
+            .position(|bound| *bound == seconds)
+            .unwrap()
+    };
+
+    let (count, buckets, sum) = observe(probe("sleep 0.3", Duration::from_secs(1))).await;
+    assert_eq!(count, 1);
+    assert!(
+        (Duration::from_millis(300)..Duration::from_millis(500)).contains(&sum),
+        "{sum:?}",
+    );
+    assert_eq!(buckets[bucket(0.25)], 0);
+    assert_eq!(buckets[bucket(0.5)], 1);
+
+    // timed out attempts are recorded up to the timeout
+    let (count, _, sum) = observe(probe("sleep 5", Duration::from_millis(200))).await;
+    assert_eq!(count, 1);
+    assert!(
+        (Duration::from_millis(200)..Duration::from_millis(400)).contains(&sum),
+        "{sum:?}",
+    );
+}
+
+#[tokio::test]
+async fn test_budget() {
+    use futures::StreamExt;
+
+    let context = context();
+    let probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+        "exec": {"command": ["sleep", "10"]},
+        "period_seconds": 1,
+        "timeout_seconds": 10,
+        "failure_threshold": 1,
+        // capped by the period
+        "budget_seconds": 5,
+    }))
+    .unwrap();
+    let report = probe::Report::default();
+
+    let start = tokio::time::Instant::now();
+    let status = std::pin::pin!(probe.watch(&context, &report)).next().await;
+    assert_eq!(status, Some(probe::Status::Failure));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert_eq!(
+        report.last_error.lock().unwrap().as_deref(),
+        Some("ran into the next period"),
+    );
+}
+
+#[tokio::test]
+async fn test_tcp_socket() {
+    use futures::StreamExt;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let context = context();
+    let probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+        "tcp_socket": {"host": "127.0.0.1", "port": port},
+        "period_seconds": 1,
+        "failure_threshold": 1,
+    }))
+    .unwrap();
+
+    let report = probe::Report::default();
+    let status = std::pin::pin!(probe.watch(&context, &report)).next().await;
+    assert_eq!(status, Some(probe::Status::Success));
+
+    drop(listener);
+    let report = probe::Report::default();
+    let status = std::pin::pin!(probe.watch(&context, &report)).next().await;
+    assert_eq!(status, Some(probe::Status::Failure));
+}
+
+#[tokio::test]
+async fn test_dns() {
+    let context = context();
+    let call = async |method: serde_json::Value| {
+        let mut probe =
+            serde_json::from_value::<probe::Probe>(serde_json::json!({ "dns": method })).unwrap();
+        probe.timeout = Duration::from_secs(5);
+        probe.call(&context).await
+    };
+
+    call(serde_json::json!({"host": "localhost"}))
+        .await
+        .unwrap();
+    call(serde_json::json!({"host": "localhost", "expect": ["127.0.0.1", "::1"]}))
+        .await
+        .unwrap();
+    let e = call(serde_json::json!({"host": "localhost", "expect": ["192.0.2.1"]}))
+        .await
+        .unwrap_err();
+    assert!(e.to_string().starts_with("localhost resolved to "), "{e}");
+    // https://www.rfc-editor.org/rfc/rfc6761#section-6.4
+    assert!(
+        call(serde_json::json!({"host": "healthzd.invalid"}))
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_grpc() {
+    use futures::StreamExt;
+
+    let (reporter, service) = tonic_health::server::health_reporter();
+    reporter
+        .set_service_status("serving", tonic_health::ServingStatus::Serving)
+        .await;
+    reporter
+        .set_service_status("not_serving", tonic_health::ServingStatus::NotServing)
+        .await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+
+    let context = context();
+    let probe = |service: &str| {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "grpc": {"host": "127.0.0.1", "port": port, "service": service},
+            "period_seconds": 1,
+            "timeout_seconds": 1,
+            "failure_threshold": 1,
+        }))
+        .unwrap()
+    };
+
+    probe("serving").call(&context).await.unwrap();
+    let e = probe("unknown").call(&context).await.unwrap_err();
+    assert!(e.to_string().starts_with("grpc-status 5: "), "{e}");
+
+    let probe = probe("not_serving");
+    let report = probe::Report::default();
+    let status = std::pin::pin!(probe.watch(&context, &report)).next().await;
+    assert_eq!(status, Some(probe::Status::Failure));
+    assert_eq!(
+        report.last_error.lock().unwrap().as_deref(),
+        Some("NotServing"),
+    );
+
+    // bracketed or not
+    let (_, service) = tonic_health::server::health_reporter();
+    let listener = tokio::net::TcpListener::bind("[::1]:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+    for host in ["::1", "[::1]"] {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "grpc": {"host": host, "port": port},
+            "timeout_seconds": 1,
+        }))
+        .unwrap()
+        .call(&context)
+        .await
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_metrics() {
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    let mut fixture = Fixture::new(true, false, false);
+    // every attempt runs into the timeout
+    fixture.target.liveness_probe.as_mut().unwrap().method = exec_probe(&["sleep", "1"]).method;
+    let targets: std::sync::Arc<[_]> = std::sync::Arc::from([(
+        fixture.target.clone(),
+        std::sync::Arc::new(super::Status::default()),
+    )]);
+    let (target, status) = &targets[0];
+    let _ = tokio::time::timeout(
+        Duration::from_millis(250),
+        super::update(&fixture.context, target, status, async {}),
+    )
+    .await;
+
+    let app = super::router(
+        &tokio::sync::watch::channel(targets.clone()).1,
+        &None,
+        &Default::default(),
+        &Default::default(),
+        false,
+        true,
+        true,
+    );
+    let request = http::Request::get("/metrics")
+        .body(axum::body::Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(
+        response.headers()[http::header::CONTENT_TYPE],
+        "text/plain; version=0.0.4; charset=utf-8",
+    );
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body = std::str::from_utf8(&body).unwrap();
+    assert!(
+        body.contains("healthzd_probe_success{name=\"test\",kind=\"liveness\"} 0\n"),
+        "{body}",
+    );
+    let failures = body
+        .lines()
+        .find_map(|line| {
+            line.strip_prefix("healthzd_probe_failures_total{name=\"test\",kind=\"liveness\"} ")
+        })
+        .unwrap();
+    assert!(failures.parse::<u64>().unwrap() > 0, "{body}");
+    assert!(
+        body.contains(&format!(
+            "healthzd_probe_hangs_total{{name=\"test\",kind=\"liveness\"}} {failures}\n"
+        )),
+        "{body}",
+    );
+    assert!(!body.contains("kind=\"readiness\""), "{body}");
+    assert!(
+        body.contains("# TYPE healthzd_probe_duration_seconds histogram\n"),
+        "{body}",
+    );
+    let count = body
+        .lines()
+        .find_map(|line| {
+            line.strip_prefix(
+                "healthzd_probe_duration_seconds_bucket{name=\"test\",kind=\"liveness\",le=\"+Inf\"} ",
+            )
+        })
+        .unwrap();
+    assert_eq!(count, failures, "{body}");
+    let transition = body
+        .lines()
+        .find_map(|line| {
+            line.strip_prefix(
+                "healthzd_probe_last_transition_timestamp_seconds{name=\"test\",kind=\"liveness\"} ",
+            )
+        })
+        .unwrap();
+    let elapsed =
+        std::time::SystemTime::UNIX_EPOCH + Duration::from_secs_f64(transition.parse().unwrap());
+    assert!(
+        elapsed.elapsed().unwrap() < Duration::from_secs(10),
+        "{body}"
+    );
+
+    // judged as /ready does, not by the last result alone
+    let mut fixture = Fixture::new(false, true, false);
+    fixture
+        .target
+        .readiness_probe
+        .as_mut()
+        .unwrap()
+        .require_startup = true;
+    let status = super::Status::default();
+    status.set_ready(true);
+    let body = super::metrics(
+        &[(fixture.target, std::sync::Arc::new(status))],
+        &Default::default(),
+    );
+    assert!(
+        body.contains("healthzd_probe_success{name=\"test\",kind=\"readiness\"} 0\n"),
+        "{body}",
+    );
+}
+
+#[tokio::test]
+async fn test_shutdown() {
+    use futures::FutureExt;
+
+    let targets: std::sync::Arc<[_]> = std::sync::Arc::from([(
+        serde_json::from_value::<super::Target>(serde_json::json!({"name": "test"})).unwrap(),
+        std::sync::Arc::new(super::Status::default()),
+    )]);
+    targets[0].1.set_ready(true);
+    let draining = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+    let shutdown = super::shutdown(
+        draining.clone(),
+        rx.map(drop),
+        Some(Duration::from_millis(500)),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(super::serve(
+        listener,
+        super::router(
+            &tokio::sync::watch::channel(targets.clone()).1,
+            &None,
+            &draining,
+            &Default::default(),
+            false,
+            true,
+            true,
+        ),
+        shutdown,
+    ));
+
+    let client = hyper::client::<http_body_util::Empty<bytes::Bytes>>(
+        hyper::tls_config(&[], true, None).unwrap(),
+        hyper::Resolver::new(None),
+        None,
+        hyper::ConnectOrder::default(),
+        hyper::HttpVersion::Auto,
+    );
+    let get = async |path: &str| {
+        client
+            .get(format!("http://127.0.0.1:{port}{path}").parse().unwrap())
+            .await
+            .unwrap()
+            .status()
+    };
+
+    assert_eq!(get("/ready").await, http::StatusCode::OK);
+    tx.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(get("/ready").await, http::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(get("/live").await, http::StatusCode::OK);
+    assert!(!server.is_finished());
+    drop(client);
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+#[test]
+fn test_config_expand() {
+    let expand = |data| {
+        healthzd::config::expand(data, |name| {
+            (name == "SERVICE_HOST").then(|| "db.internal".to_string())
+        })
+    };
+    assert_eq!(
+        expand(r#"host = "${SERVICE_HOST}""#).unwrap(),
+        r#"host = "db.internal""#,
+    );
+    assert_eq!(
+        expand(r#"port = ${SERVICE_PORT:-5432}"#).unwrap(),
+        "port = 5432",
+    );
+    assert_eq!(
+        expand(r#"host = "${SERVICE_HOST:-localhost}""#).unwrap(),
+        r#"host = "db.internal""#,
+    );
+    assert_eq!(
+        expand("a$$b $c $${SERVICE_HOST}").unwrap(),
+        "a$b $c ${SERVICE_HOST}"
+    );
+    let e = expand("${SERVICE_PORT}").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "environment variable SERVICE_PORT is not set"
+    );
+    assert!(expand("${SERVICE_HOST").is_err());
+
+    let temp = tempfile::tempdir().unwrap();
+    let path = temp.path().join("healthzd.toml");
+    std::fs::write(&path, "[[target]]\nname = \"${HEALTHZD_TEST_UNDEFINED}\"\n").unwrap();
+    let e = healthzd::config::load(&path).map(drop).unwrap_err();
+    assert!(
+        format!("{e:#}").contains("HEALTHZD_TEST_UNDEFINED is not set"),
+        "{e:#}",
+    );
+}
+
+#[test]
+fn test_config_yaml() {
+    let temp = tempfile::tempdir().unwrap();
+    let toml = temp.path().join("healthzd.toml");
+    std::fs::write(
+        &toml,
+        r#"
+[[target]]
+name = "app"
+readiness_probe = { http_get = { port = 8080, path = "/ready", http_headers = { x-probe = "1" } }, period_seconds = 5 }
+
+[[target]]
+name = "worker"
+liveness_probe = { exec = { command = ["sh", "-c", "true"], env = { FOO = "bar" } }, failure_threshold = 2 }
+"#,
+    )
+    .unwrap();
+    let yaml = temp.path().join("healthzd.yaml");
+    std::fs::write(
+        &yaml,
+        r#"
+target:
+  - name: app
+    readiness_probe:
+      http_get:
+        port: 8080
+        path: /ready
+        http_headers:
+          x-probe: "1"
+      period_seconds: 5
+  - name: worker
+    liveness_probe:
+      exec:
+        command: [sh, -c, "true"]
+        env: { FOO: bar }
+      failure_threshold: 2
+"#,
+    )
+    .unwrap();
+
+    let load = |path| {
+        healthzd::config::load(path)
+            .unwrap()
+            .into_iter()
+            .map(|(target, _)| serde_json::to_value(target).unwrap())
+            .collect::<Vec<_>>()
+    };
+    let targets = load(&toml);
+    assert_eq!(targets.len(), 2);
+    assert_eq!(targets, load(&yaml));
+}
+
+#[test]
+fn test_config_dir() {
+    let temp = tempfile::tempdir().unwrap();
+    let target = |name: &str| {
+        format!(
+            "[[target]]\nname = \"{name}\"\nreadiness_probe = {{ exec = {{ command = [\"true\"] }} }}\n"
+        )
+    };
+    std::fs::write(temp.path().join("b.toml"), target("team-b")).unwrap();
+    std::fs::write(
+        temp.path().join("a.yaml"),
+        "target:\n  - name: team-a\n    readiness_probe: { exec: { command: [\"true\"] } }\n",
+    )
+    .unwrap();
+    std::fs::write(temp.path().join("README.md"), "not a config").unwrap();
+    let names = |targets: Vec<(super::Target, serde_json::Value)>| {
+        targets
+            .into_iter()
+            .map(|(target, _)| target.name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(healthzd::config::load(temp.path()).unwrap()),
+        ["team-a", "team-b"],
+    );
+
+    std::fs::write(temp.path().join("c.toml"), target("team-a")).unwrap();
+    let e = healthzd::config::load(temp.path()).map(drop).unwrap_err();
+    assert!(
+        e.to_string().starts_with("duplicate target team-a in "),
+        "{e}",
+    );
+    assert!(e.to_string().ends_with("c.toml"), "{e}");
+}
+
+#[tokio::test]
+async fn test_reload() {
+    use tower::ServiceExt;
+
+    let temp = tempfile::tempdir().unwrap();
+    let path = temp.path().join("healthzd.toml");
+    let target = |name: &str, command: &str| {
+        format!(
+            "[[target]]\nname = \"{name}\"\n\
+             readiness_probe = {{ exec = {{ command = [\"{command}\"] }}, period_seconds = 1 }}\n",
+        )
+    };
+    std::fs::write(&path, target("a", "true")).unwrap();
+
+    let fixture = Fixture::new(false, false, false);
+    let mut runner = super::Runner {
+        context: std::sync::Arc::new(fixture.context),
+        isolated: None,
+        tasks: tokio::task::JoinSet::new(),
+        watchdog: None,
+        loaded: Default::default(),
+        reloads: Default::default(),
+    };
+    let prepare = |target| (target, std::sync::Arc::new(super::Status::default()));
+    let targets = tokio::sync::watch::Sender::new(runner.start(
+        &[],
+        healthzd::config::load(&path).unwrap(),
+        &prepare,
+    ));
+    let app = super::router(
+        &targets.subscribe(),
+        &None,
+        &Default::default(),
+        &Default::default(),
+        false,
+        true,
+        true,
+    );
+    let ready = async || {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let request = http::Request::get("/ready")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    };
+    assert_eq!(ready().await, http::StatusCode::OK);
+    let a = targets.borrow()[0].1.clone();
+
+    // a broken config leaves the running targets alone
+    std::fs::write(&path, "[[target]]\n").unwrap();
+    super::reload(&path, None, &mut runner, &[], &prepare, &targets);
+    assert_eq!(targets.borrow().len(), 1);
+
+    std::fs::write(
+        &path,
+        format!("{}{}", target("a", "true"), target("b", "false")),
+    )
+    .unwrap();
+    super::reload(&path, None, &mut runner, &[], &prepare, &targets);
+    assert_eq!(ready().await, http::StatusCode::SERVICE_UNAVAILABLE);
+    // unchanged targets keep their status
+    assert!(std::sync::Arc::ptr_eq(&targets.borrow()[0].1, &a));
+
+    std::fs::write(&path, target("a", "true")).unwrap();
+    super::reload(&path, None, &mut runner, &[], &prepare, &targets);
+    assert_eq!(ready().await, http::StatusCode::OK);
+    assert_eq!(runner.loaded.len(), 1);
+
+    let metrics = super::metrics(&[], &runner.reloads);
+    assert!(
+        metrics.contains("healthzd_config_reload_total{result=\"success\"} 2\n"),
+        "{metrics}",
+    );
+    assert!(
+        metrics.contains("healthzd_config_reload_total{result=\"failure\"} 1\n"),
+        "{metrics}",
+    );
+}
+
+#[tokio::test]
+async fn test_isolate_clients() {
+    let peers = std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashSet::new()));
+    let app = axum::Router::new().route(
+        "/",
+        axum::routing::get({
+            let peers = peers.clone();
+            async move |axum::extract::ConnectInfo(peer): axum::extract::ConnectInfo<
+                std::net::SocketAddr,
+            >| {
+                peers.lock().unwrap().insert(peer);
+                "ok"
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+        )
+        .await
+    });
+
+    let mut runner = super::Runner {
+        context: std::sync::Arc::new(context()),
+        isolated: Some((
+            hyper::tls_config(&[], true, None).unwrap(),
+            hyper::Resolver::new(None),
+            hyper::ConnectOrder::default(),
+        )),
+        tasks: tokio::task::JoinSet::new(),
+        watchdog: None,
+        loaded: Default::default(),
+        reloads: Default::default(),
+    };
+    let target = |name: &str| {
+        let target = super::parse_target(
+            &serde_json::json!({
+                "name": name,
+                "readiness_probe": {
+                    "http_get": { "host": addr.ip().to_string(), "port": addr.port() },
+                    "period_seconds": 1,
+                },
+            })
+            .to_string(),
+        )
+        .unwrap();
+        (target, std::sync::Arc::new(super::Status::default()))
+    };
+    // one after the other, so that b would pick up the connection left idle by a if they shared a pool
+    let targets = [target("a"), target("b")];
+    runner.spawn(&targets, targets[0].clone());
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    runner.spawn(&targets, targets[1].clone());
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    for (target, status) in &targets {
+        assert!(super::is_ready(target, status), "{}", target.name);
+    }
+    assert_eq!(peers.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_http_method() {
+    let app = axum::Router::new().route(
+        "/",
+        axum::routing::any(async |method: http::Method| {
+            if method == http::Method::HEAD {
+                http::StatusCode::OK
+            } else {
+                http::StatusCode::METHOD_NOT_ALLOWED
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let call = async |method: Option<&str>| {
+        let mut probe = serde_json::json!({
+            "http_get": {"host": addr.ip().to_string(), "port": addr.port()},
+            "timeout_seconds": 1,
+        });
+        if let Some(method) = method {
+            probe["http_get"]["http_method"] = method.into();
+        }
+        serde_json::from_value::<probe::Probe>(probe)
+            .unwrap()
+            .call(&context)
+            .await
+    };
+
+    call(Some("HEAD")).await.unwrap();
+    assert_eq!(
+        call(None).await.unwrap_err().to_string(),
+        "405 Method Not Allowed",
+    );
+    assert_eq!(
+        call(Some("POST")).await.unwrap_err().to_string(),
+        "405 Method Not Allowed",
+    );
+    assert!(
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {"port": 80, "http_method": "NOT A METHOD"},
+        }))
+        .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_expected_status() {
+    let app = axum::Router::new().route(
+        "/",
+        axum::routing::get(async || http::StatusCode::IM_A_TEAPOT),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let probe = |expected_status: serde_json::Value| {
+        let mut probe = serde_json::json!({
+            "http_get": {"host": addr.ip().to_string(), "port": addr.port()},
+            "timeout_seconds": 1,
+        });
+        if !expected_status.is_null() {
+            probe["http_get"]["expected_status"] = expected_status;
+        }
+        serde_json::from_value::<probe::Probe>(probe)
+    };
+    let call = async |expected_status| probe(expected_status).unwrap().call(&context).await;
+
+    assert!(call(serde_json::Value::Null).await.is_err());
+    call(serde_json::json!(418)).await.unwrap();
+    assert!(call(serde_json::json!(200)).await.is_err());
+    call(serde_json::json!([200, 418])).await.unwrap();
+    assert!(call(serde_json::json!([200, 204])).await.is_err());
+    call(serde_json::json!("400-499")).await.unwrap();
+    call(serde_json::json!([200, "410-420"])).await.unwrap();
+    assert!(call(serde_json::json!("200-299")).await.is_err());
+    assert!(probe(serde_json::json!("299-200")).is_err());
+    assert!(probe(serde_json::json!("200-1000")).is_err());
+    assert!(probe(serde_json::json!("ok")).is_err());
+}
+
+#[tokio::test]
+async fn test_pending() {
+    use futures::StreamExt;
+
+    let hits = std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashMap::new()));
+    let app = axum::Router::new().route(
+        "/{retry_after}",
+        axum::routing::get({
+            let hits = hits.clone();
+            async move |axum::extract::Path(retry_after): axum::extract::Path<String>| {
+                *hits.lock().unwrap().entry(retry_after.clone()).or_insert(0) += 1;
+                let retry_after = match &*retry_after {
+                    "delta" => Some("1".to_owned()),
+                    "date" => Some(httpdate::fmt_http_date(
+                        std::time::SystemTime::now() + Duration::from_secs(2),
+                    )),
+                    "garbage" => Some("soon".to_owned()),
+                    _ => None,
+                };
+                (
+                    http::StatusCode::ACCEPTED,
+                    axum::response::AppendHeaders(
+                        retry_after.map(|value| (http::header::RETRY_AFTER, value)),
+                    ),
+                )
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let watch = async |retry_after: &str| {
+        let probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {
+                "host": addr.ip().to_string(),
+                "port": addr.port(),
+                "path": format!("/{retry_after}"),
+                "pending_codes": [202],
+            },
+            "timeout_seconds": 1,
+            "period_seconds": 10,
+        }))
+        .unwrap();
+        let report = probe::Report::default();
+        let mut stream = std::pin::pin!(probe.watch(&context, &report));
+        // neither a success nor a failure, so there is no status to report
+        assert!(
+            tokio::time::timeout(Duration::from_millis(3500), stream.next())
+                .await
+                .is_err()
+        );
+        assert_eq!(report.checks.load(Ordering::Relaxed), 0);
+        assert_eq!(report.failures.load(Ordering::Relaxed), 0);
+    };
+    futures::future::join4(
+        watch("delta"),
+        watch("date"),
+        watch("garbage"),
+        watch("none"),
+    )
+    .await;
+
+    let hits = hits.lock().unwrap();
+    // rescheduled by Retry-After instead of waiting for the period
+    assert!(hits["delta"] >= 3, "{hits:?}");
+    assert!(hits["date"] >= 2, "{hits:?}");
+    assert_eq!(hits["garbage"], 1);
+    assert_eq!(hits["none"], 1);
+}
+
+#[tokio::test]
+async fn test_body_regex() {
+    let app = axum::Router::new()
+        .route("/ok", axum::routing::get(async || r#"{"status":"ok"}"#))
+        .route(
+            "/degraded",
+            axum::routing::get(async || r#"{"status":"degraded"}"#),
+        )
+        .route("/large", axum::routing::get(async || "ok".repeat(1 << 20)));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let call = async |path: &str| {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {
+                "host": addr.ip().to_string(),
+                "port": addr.port(),
+                "path": path,
+                "body_regex": r#""status":\s*"ok""#,
+            },
+            "timeout_seconds": 1,
+        }))
+        .unwrap()
+        .call(&context)
+        .await
+    };
+
+    call("/ok").await.unwrap();
+    let e = call("/degraded").await.unwrap_err();
+    assert!(e.to_string().starts_with("response does not match"), "{e}");
+    let e = call("/large").await.unwrap_err();
+    assert_eq!(e.to_string(), "length limit exceeded");
+}
+
+#[tokio::test]
+async fn test_json_path() {
+    let app = axum::Router::new()
+        .route(
+            "/ok",
+            axum::routing::get(async || r#"{"status":{"db":"ok"}}"#),
+        )
+        .route(
+            "/degraded",
+            axum::routing::get(async || r#"{"status":{"db":"degraded"}}"#),
+        )
+        .route("/other", axum::routing::get(async || r#"{"up":true}"#))
+        .route("/text", axum::routing::get(async || "ok"));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let call = async |path: &str| {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {
+                "host": addr.ip().to_string(),
+                "port": addr.port(),
+                "path": path,
+                "json_path": "$.status.db",
+                "json_value": "ok",
+            },
+            "timeout_seconds": 1,
+        }))
+        .unwrap()
+        .call(&context)
+        .await
+    };
+
+    call("/ok").await.unwrap();
+    let e = call("/degraded").await.unwrap_err();
+    assert_eq!(e.to_string(), r#"$.status.db is "degraded", expected "ok""#);
+    let e = call("/other").await.unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "$.status.db: nodelist expected to contain one entry, but is empty",
+    );
+    let e = call("/text").await.unwrap_err();
+    assert_eq!(
+        format!("{e:#}"),
+        "response is not JSON: expected value at line 1 column 1",
+    );
+}
+
+#[tokio::test]
+async fn test_request_body() {
+    let app = axum::Router::new().route(
+        "/graphql",
+        axum::routing::post(async |headers: http::HeaderMap, body: String| {
+            if headers
+                .get(http::header::CONTENT_TYPE)
+                .is_some_and(|value| value == "application/json")
+                && body == r#"{"query": "{ health }"}"#
+            {
+                (http::StatusCode::OK, body)
+            } else {
+                (http::StatusCode::BAD_REQUEST, body)
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let call = async |options: serde_json::Value| {
+        let mut method = serde_json::json!({
+            "host": addr.ip().to_string(),
+            "port": addr.port(),
+            "path": "/graphql",
+        });
+        method
+            .as_object_mut()
+            .unwrap()
+            .extend(options.as_object().unwrap().clone());
+        let mut probe =
+            serde_json::from_value::<probe::Probe>(serde_json::json!({ "http_get": method }))?;
+        probe.timeout = Duration::from_secs(1);
+        probe.call(&context).await
+    };
+
+    call(serde_json::json!({
+        "body": r#"{"query": "{ health }"}"#,
+        "content_type": "application/json",
+        // echoed back
+        "body_regex": "health",
+    }))
+    .await
+    .unwrap();
+    let e = call(serde_json::json!({"body": r#"{"query": "{ health }"}"#}))
+        .await
+        .unwrap_err();
+    assert_eq!(e.to_string(), "400 Bad Request");
+    let e = call(serde_json::json!({})).await.unwrap_err();
+    assert_eq!(e.to_string(), "405 Method Not Allowed");
+    assert!(
+        call(serde_json::json!({
+            "body": "{}",
+            "template": {"body": "{}"},
+        }))
+        .await
+        .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_http_version() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // speaks nothing but HTTP/1.1
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                if buf[..n].starts_with(b"GET / HTTP/1.1\r\n") {
+                    let _ = stream
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                        )
+                        .await;
+                } else {
+                    let _ = stream
+                        .write_all(b"HTTP/1.1 505 HTTP Version Not Supported\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                        .await;
+                }
+            });
+        }
+    });
+
+    let context = context();
+    let call = async |http_version: &str| {
+        let mut probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {
+                "host": addr.ip().to_string(),
+                "port": addr.port(),
+                "http_version": http_version,
+            },
+        }))
+        .unwrap();
+        probe.timeout = Duration::from_secs(1);
+        probe.call(&context).await
+    };
+
+    call("auto").await.unwrap();
+    call("http1").await.unwrap();
+    assert!(call("http2").await.is_err());
+}
+
+#[tokio::test]
+async fn test_insecure_skip_tls_verify() {
+    let temp = tempfile::tempdir().unwrap();
+    let certified = rcgen::generate_simple_self_signed(["localhost".to_string()]).unwrap();
+    let (cert, key) = write_pem(
+        temp.path(),
+        "server",
+        &certified.cert,
+        &certified.signing_key,
+    );
+    let port = serve_tls(
+        hyper::server_tls_config(&cert, &key).unwrap(),
+        axum::Router::new().route("/", axum::routing::get(async || "ok")),
+    )
+    .await;
+
+    let context = context();
+    let call = async |insecure_skip_tls_verify: bool| {
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {
+                "scheme": "HTTPS",
+                "port": port,
+                "insecure_skip_tls_verify": insecure_skip_tls_verify,
+            },
+            "timeout_seconds": 1,
+        }))
+        .unwrap()
+        .call(&context)
+        .await
+    };
+
+    let e = call(false).await.unwrap_err();
+    assert!(
+        format!("{e:#}").contains("invalid peer certificate"),
+        "{e:#}"
+    );
+    call(true).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_auth() {
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+    let temp = tempfile::tempdir().unwrap();
+    let ca = ca();
+    let ca_cert = temp.path().join("ca.crt");
+    std::fs::write(&ca_cert, ca.pem()).unwrap();
+    let (server_cert, server_key) = issue(&ca, "localhost");
+    let (client_cert, client_key) = issue(&ca, "client");
+    let client_auth = write_pem(temp.path(), "client", &client_cert, &client_key);
+
+    let provider = std::sync::Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(ca.der().clone()).unwrap();
+    let tls_config = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_client_cert_verifier(
+            rustls::server::WebPkiClientVerifier::builder_with_provider(
+                std::sync::Arc::new(roots),
+                provider,
+            )
+            .build()
+            .unwrap(),
+        )
+        .with_single_cert(
+            vec![CertificateDer::from(server_cert.der().to_vec())],
+            PrivateKeyDer::try_from(server_key.serialize_der()).unwrap(),
+        )
+        .unwrap();
+    let port = serve_tls(
+        tls_config,
+        axum::Router::new().route("/", axum::routing::get(async || "ok")),
+    )
+    .await;
+
+    let probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+        "http_get": {"scheme": "HTTPS", "port": port},
+        "timeout_seconds": 1,
+    }))
+    .unwrap();
+    let context = |client_auth: Option<(&Path, &Path)>| {
+        probe::Context::new(
+            hyper::tls_config(std::slice::from_ref(&ca_cert), false, client_auth).unwrap(),
+            hyper::Resolver::new(None),
+            hyper::ConnectOrder::default(),
+        )
+    };
+
+    assert!(probe.call(&context(None)).await.is_err());
+    probe
+        .call(&context(Some((&client_auth.0, &client_auth.1))))
+        .await
+        .unwrap();
+    // surfaced when loading, rather than on the first request
+    assert!(hyper::tls_config(&[], true, Some((&client_auth.1, &client_auth.1))).is_err());
+}
+
+#[tokio::test]
+async fn test_ca_cert() {
+    use clap::Parser;
+
+    let temp = tempfile::tempdir().unwrap();
+    let ca = ca();
+    let (cert, key) = issue(&ca, "localhost");
+    let (cert, key) = write_pem(temp.path(), "server", &cert, &key);
+    let port = serve_tls(
+        hyper::server_tls_config(&cert, &key).unwrap(),
+        axum::Router::new().route("/", axum::routing::get(async || "ok")),
+    )
+    .await;
+    // along with an unrelated CA in the same file
+    let ca_cert = temp.path().join("ca.crt");
+    std::fs::write(&ca_cert, format!("{}{}", self::ca().pem(), ca.pem())).unwrap();
+
+    let args = super::Args::try_parse_from([
+        "healthzd",
+        "--once",
+        "--ca-file",
+        ca_cert.to_str().unwrap(),
+        "--ca-only",
+    ])
+    .unwrap();
+    assert_eq!(args.ca_cert, std::slice::from_ref(&ca_cert));
+    assert!(args.no_default_roots);
+
+    let probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+        "http_get": {"scheme": "HTTPS", "port": port},
+        "timeout_seconds": 1,
+    }))
+    .unwrap();
+    let context = |ca_certs: &[PathBuf]| {
+        probe::Context::new(
+            hyper::tls_config(ca_certs, true, None).unwrap(),
+            hyper::Resolver::new(None),
+            hyper::ConnectOrder::default(),
+        )
+    };
+    assert!(probe.call(&context(&[])).await.is_err());
+    probe.call(&context(&[ca_cert])).await.unwrap();
+
+    let empty = temp.path().join("empty.crt");
+    std::fs::write(&empty, "").unwrap();
+    let e = hyper::tls_config(&[empty], true, None).unwrap_err();
+    assert!(e.to_string().starts_with("no certificates found"), "{e}");
+}
+
+#[tokio::test]
+async fn test_separate_binds() {
+    let targets: super::Targets = std::sync::Arc::from([]);
+    let targets = tokio::sync::watch::channel(targets).1;
+    let serve = async |app| {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(super::serve(listener, app, futures::future::pending()));
+        port
+    };
+    let live_port = serve(super::live_router(&targets, &None)).await;
+    let ready_port = serve(super::ready_router(&targets, &Default::default(), false)).await;
+    let port = serve(super::router(
+        &targets,
+        &None,
+        &Default::default(),
+        &Default::default(),
+        false,
+        false,
+        false,
+    ))
+    .await;
+
+    let client = hyper::client::<http_body_util::Empty<bytes::Bytes>>(
+        hyper::tls_config(&[], true, None).unwrap(),
+        hyper::Resolver::new(None),
+        None,
+        hyper::ConnectOrder::default(),
+        hyper::HttpVersion::Auto,
+    );
+    let get = async |port: u16, path: &str| {
+        client
+            .get(format!("http://127.0.0.1:{port}{path}").parse().unwrap())
+            .await
+            .unwrap()
+            .status()
+    };
+
+    assert_eq!(get(live_port, "/live").await, http::StatusCode::OK);
+    assert_eq!(get(live_port, "/ready").await, http::StatusCode::NOT_FOUND);
+    assert_eq!(get(ready_port, "/ready").await, http::StatusCode::OK);
+    assert_eq!(get(ready_port, "/live").await, http::StatusCode::NOT_FOUND);
+    assert_eq!(get(port, "/healthz").await, http::StatusCode::OK);
+    assert_eq!(get(port, "/live").await, http::StatusCode::NOT_FOUND);
+    assert_eq!(get(port, "/ready").await, http::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_layered() {
+    let release = std::sync::Arc::new(tokio::sync::Notify::new());
+    let app = axum::Router::new()
+        .route(
+            "/wait",
+            axum::routing::get({
+                let release = release.clone();
+                async move || release.notified().await
+            }),
+        )
+        .route(
+            "/slow",
+            axum::routing::get(async || tokio::time::sleep(Duration::from_secs(10)).await),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(super::serve(
+        listener,
+        super::layered(app, Some(1), Some(Duration::from_millis(200))),
+        futures::future::pending(),
+    ));
+
+    let client = hyper::client::<http_body_util::Empty<bytes::Bytes>>(
+        hyper::tls_config(&[], true, None).unwrap(),
+        hyper::Resolver::new(None),
+        None,
+        hyper::ConnectOrder::default(),
+        hyper::HttpVersion::Auto,
+    );
+    let get = async |path: &str| {
+        client
+            .get(format!("http://127.0.0.1:{port}{path}").parse().unwrap())
+            .await
+            .unwrap()
+            .status()
+    };
+
+    assert_eq!(get("/slow").await, http::StatusCode::REQUEST_TIMEOUT);
+    let (first, second) = futures::future::join(get("/wait"), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        // the first one is still in flight
+        let status = get("/wait").await;
+        release.notify_one();
+        status
+    })
+    .await;
+    assert_eq!(first, http::StatusCode::OK);
+    assert_eq!(second, http::StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn test_bind() {
+    let held = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = held.local_addr().unwrap();
+
+    let start = std::time::Instant::now();
+    let e = super::bind(addr, None).await.unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::AddrInUse);
+    assert!(start.elapsed() < Duration::from_millis(100));
+
+    // released while retrying
+    let release = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(300));
+        drop(held);
+    });
+    let listener = super::bind(addr, Some(Duration::from_secs(5)))
+        .await
+        .unwrap();
+    assert_eq!(listener.local_addr().unwrap(), addr);
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    release.join().unwrap();
+}
+
+#[tokio::test]
+async fn test_unix_socket() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let temp = tempfile::tempdir().unwrap();
+    let path = temp.path().join("healthzd.sock");
+    // left behind by an earlier run
+    std::fs::write(&path, "").unwrap();
+
+    let targets: super::Targets = std::sync::Arc::from([]);
+    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(super::serve_unix(
+        super::bind_unix(&path).unwrap(),
+        path.clone(),
+        super::router(
+            &tokio::sync::watch::channel(targets).1,
+            &None,
+            &Default::default(),
+            &Default::default(),
+            false,
+            true,
+            true,
+        ),
+        async move {
+            rx.await.ok();
+        },
+    ));
+
+    let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+    stream
+        .write_all(b"GET /live HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+
+    tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn test_allow_cidr_over_unix_socket() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let temp = tempfile::tempdir().unwrap();
+    let path = temp.path().join("healthzd.sock");
+    let app = super::allow_cidr(
+        axum::Router::new().route("/admin", axum::routing::get(async || "ok")),
+        vec!["10.0.0.0/8".parse().unwrap()],
+    );
+    tokio::spawn(super::serve_unix(
+        super::bind_unix(&path).unwrap(),
+        path.clone(),
+        app.clone(),
+        futures::future::pending(),
+    ));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(super::serve(listener, app, futures::future::pending()));
+
+    let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+    stream
+        .write_all(b"GET /admin HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+
+    let client = hyper::client::<http_body_util::Empty<bytes::Bytes>>(
+        hyper::tls_config(&[], true, None).unwrap(),
+        hyper::Resolver::new(None),
+        None,
+        hyper::ConnectOrder::default(),
+        hyper::HttpVersion::Auto,
+    );
+    let response = client
+        .get(format!("http://127.0.0.1:{port}/admin").parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_basic_auth() {
+    let app = axum::Router::new().route(
+        "/metrics",
+        axum::routing::get(async |headers: http::HeaderMap| {
+            // user:secret
+            if headers
+                .get(http::header::AUTHORIZATION)
+                .is_some_and(|value| value == "Basic dXNlcjpzZWNyZXQ=")
+            {
+                http::StatusCode::OK
+            } else {
+                http::StatusCode::UNAUTHORIZED
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let temp = tempfile::tempdir().unwrap();
+    let password = temp.path().join("password");
+    std::fs::write(&password, "secret\n").unwrap();
+
+    let context = context();
+    let probe = |options: serde_json::Value| {
+        let mut method = serde_json::json!({
+            "host": addr.ip().to_string(),
+            "port": addr.port(),
+            "path": "/metrics",
+        });
+        method
+            .as_object_mut()
+            .unwrap()
+            .extend(options.as_object().unwrap().clone());
+        serde_json::from_value::<probe::Probe>(serde_json::json!({ "http_get": method }))
+    };
+
+    let e = probe(serde_json::json!({}))
+        .unwrap()
+        .call(&context)
+        .await
+        .unwrap_err();
+    assert_eq!(e.to_string(), "401 Unauthorized");
+    let e = probe(serde_json::json!({
+        "basic_auth": {"username": "user", "password": "wrong"},
+    }))
+    .unwrap()
+    .call(&context)
+    .await
+    .unwrap_err();
+    assert_eq!(e.to_string(), "401 Unauthorized");
+    let authorized = probe(serde_json::json!({
+        "basic_auth": {
+            "username": "user",
+            "password": format!("{{{{file:{}}}}}", password.display()),
+        },
+    }))
+    .unwrap();
+    authorized.call(&context).await.unwrap();
+    // only the name of the header is shown
+    let value = serde_json::to_string(&authorized).unwrap();
+    assert!(value.contains("authorization"), "{value}");
+    assert!(!value.contains("secret"), "{value}");
+
+    assert!(
+        probe(serde_json::json!({
+            "basic_auth": {"username": "user", "password": "secret"},
+            "http_headers": {"authorization": "Bearer token"},
+        }))
+        .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_bearer_token_file() {
+    let received = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let app = axum::Router::new().route(
+        "/",
+        axum::routing::get({
+            let received = received.clone();
+            async move |headers: http::HeaderMap| {
+                received
+                    .lock()
+                    .unwrap()
+                    .push(headers.get(http::header::AUTHORIZATION).cloned());
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let temp = tempfile::tempdir().unwrap();
+    let token = temp.path().join("token");
+
+    let context = context();
+    let probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+        "http_get": {
+            "host": addr.ip().to_string(),
+            "port": addr.port(),
+            "bearer_token_file": token,
+        },
+    }))
+    .unwrap();
+
+    let e = probe.call(&context).await.unwrap_err();
+    assert_eq!(e.to_string(), format!("failed to read {}", token.display()));
+    std::fs::write(&token, "first \n").unwrap();
+    probe.call(&context).await.unwrap();
+    // rotated
+    std::fs::write(&token, "second\n").unwrap();
+    probe.call(&context).await.unwrap();
+    std::fs::write(&token, "\n").unwrap();
+    let e = probe.call(&context).await.unwrap_err();
+    assert_eq!(e.to_string(), format!("{} is empty", token.display()));
+
+    assert_eq!(
+        *received.lock().unwrap(),
+        [
+            Some(http::HeaderValue::from_static("Bearer first")),
+            Some(http::HeaderValue::from_static("Bearer second")),
+        ],
+    );
+    assert!(
+        serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "http_get": {
+                "bearer_token_file": token,
+                "basic_auth": {"username": "user", "password": "secret"},
+            },
+        }))
+        .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_ipv6_host() {
+    let uri = |method: serde_json::Value| {
+        let probe =
+            serde_json::from_value::<probe::Probe>(serde_json::json!({ "http_get": method }))
+                .unwrap();
+        let probe::Method::HttpGet { uris, .. } = probe.method else {
+            unreachable!();
+        };
+        uris[0].0.to_string()
+    };
+    assert_eq!(
+        uri(serde_json::json!({"host": "::1", "port": 8080})),
+        "http://[::1]:8080/",
+    );
+    assert_eq!(uri(serde_json::json!({"host": "::1"})), "http://[::1]/");
+    assert_eq!(
+        uri(serde_json::json!({"host": "[::1]", "port": 8080})),
+        "http://[::1]:8080/",
+    );
+
+    let app = axum::Router::new().route("/", axum::routing::get(async || "ok"));
+    let listener = tokio::net::TcpListener::bind("[::1]:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    serde_json::from_value::<probe::Probe>(serde_json::json!({
+        "http_get": {"host": "::1", "port": port},
+    }))
+    .unwrap()
+    .call(&context)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_watch_transitions() {
+    use futures::StreamExt;
+
+    let temp = tempfile::tempdir().unwrap();
+    let count = temp.path().join("count");
+
+    let context = context();
+    // fails every third attempt, starting with the first one
+    let script = format!(
+        "n=$(cat {0} 2>/dev/null || echo 0); echo $((n + 1)) > {0}; [ $((n % 3)) -ne 0 ]",
+        count.display(),
+    );
+    let probe = probe::Probe {
+        period: Duration::from_millis(20),
+        ..exec_probe(&["sh", "-c", &script])
+    };
+    let report = probe::Report::default();
+
+    let statuses = tokio::time::timeout(
+        Duration::from_secs(5),
+        probe.watch(&context, &report).take(6).collect::<Vec<_>>(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(statuses[0], probe::Status::Failure);
+    assert!(
+        statuses.windows(2).all(|pair| pair[0] != pair[1]),
+        "{statuses:?}",
+    );
+    // the repeated successes in between were not emitted
+    assert!(report.checks.load(Ordering::Relaxed) >= 8);
+}
+
+#[tokio::test]
+async fn test_success_threshold() {
+    use futures::StreamExt;
+
+    let temp = tempfile::tempdir().unwrap();
+    let count = temp.path().join("count");
+    // fails the first attempt only
+    let script = format!(
+        "n=$(cat {0} 2>/dev/null || echo 0); echo $((n + 1)) > {0}; [ $n -ne 0 ]",
+        count.display(),
+    );
+
+    let context = context();
+    let mut probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+        "exec": {"command": ["sh", "-c", script]},
+        "success_threshold": 2,
+        "failure_threshold": 1,
+    }))
+    .unwrap();
+    // sub-second periods can only be set directly
+    probe.period = Duration::from_millis(20);
+    let report = probe::Report::default();
+
+    let mut statuses = std::pin::pin!(probe.watch(&context, &report));
+    assert_eq!(statuses.next().await, Some(probe::Status::Failure));
+    assert_eq!(statuses.next().await, Some(probe::Status::Success));
+    // ready only after the second pass
+    assert_eq!(report.checks.load(Ordering::Relaxed), 3);
+
+    let target = |kind: &str| {
+        serde_json::from_value::<super::Target>(serde_json::json!({
+            "name": "test",
+            kind: {"exec": {"command": ["true"]}, "success_threshold": 2},
+        }))
+        .map(drop)
+    };
+    target("readiness_probe").unwrap();
+    let e = target("liveness_probe").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "success_threshold of liveness_probe must be 1"
+    );
+    assert!(target("startup_probe").is_err());
+}
+
+#[tokio::test]
+async fn test_missed_schedule() {
+    use futures::StreamExt;
+
+    let temp = tempfile::tempdir().unwrap();
+    let context = context();
+    let checks = async |missed_schedule: &str| {
+        let count = temp.path().join(missed_schedule);
+        // only the first attempt is slow, running past several periods
+        let script = format!(
+            "n=$(cat {0} 2>/dev/null || echo 0); echo $((n + 1)) > {0}; [ $n -ne 0 ] || sleep 0.5",
+            count.display(),
+        );
+        let mut probe = serde_json::from_value::<probe::Probe>(serde_json::json!({
+            "exec": {"command": ["sh", "-c", script]},
+            "missed_schedule": missed_schedule,
+        }))
+        .unwrap();
+        // sub-second periods can only be set directly
+        probe.period = Duration::from_millis(50);
+        probe.timeout = Duration::from_secs(1);
+        let report = probe::Report::default();
+        let _ = tokio::time::timeout(
+            Duration::from_millis(600),
+            probe.watch(&context, &report).for_each(async |_| {}),
+        )
+        .await;
+        report.checks.load(Ordering::Relaxed)
+    };
+
+    // the slow attempt, then one per period for the remaining 100ms
+    let skipped = checks("skip").await;
+    assert!((2..=4).contains(&skipped), "{skipped}");
+    // the 10 attempts missed during the slow one are run back to back
+    let burst = checks("burst").await;
+    assert!(burst >= 10, "{burst}");
+}
+
+#[tokio::test]
+async fn test_check() {
+    let app = axum::Router::new()
+        .route("/ok", axum::routing::get(async || "ok"))
+        .route(
+            "/error",
+            axum::routing::get(async || http::StatusCode::INTERNAL_SERVER_ERROR),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let context = context();
+    let check = async |probe: serde_json::Value| {
+        serde_json::from_value::<probe::Probe>(probe)
+            .unwrap()
+            .check(&context)
+            .await
+    };
+
+    // a single failure is enough, whatever the failure_threshold
+    let exec = |command| serde_json::json!({"exec": {"command": command}, "failure_threshold": 3});
+    assert_eq!(check(exec(["true"])).await, probe::Status::Success);
+    assert_eq!(check(exec(["false"])).await, probe::Status::Failure);
+    let start = tokio::time::Instant::now();
+    assert_eq!(
+        check(serde_json::json!({
+            "exec": {"command": ["sleep", "10"]},
+            "timeout_seconds": 1,
+        }))
+        .await,
+        probe::Status::Failure,
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    let http_get = |path| {
+        serde_json::json!({
+            "http_get": {"host": addr.ip().to_string(), "port": addr.port(), "path": path},
+        })
+    };
+    assert_eq!(check(http_get("/ok")).await, probe::Status::Success);
+    assert_eq!(check(http_get("/error")).await, probe::Status::Failure);
+    let e = serde_json::from_value::<probe::Probe>(http_get("/error"))
+        .unwrap()
+        .try_check(&context)
+        .await
+        .unwrap_err();
+    assert_eq!(e.to_string(), "500 Internal Server Error");
+}
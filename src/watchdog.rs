@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct Watchdog {
+    interval: Duration,
+    epoch: Instant,
+    // one per beating task, gone once the task is dropped
+    heartbeats: Mutex<Vec<Weak<AtomicU64>>>,
+    live: AtomicBool,
+}
+
+impl Watchdog {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            epoch: Instant::now(),
+            heartbeats: Mutex::default(),
+            live: AtomicBool::new(true),
+        }
+    }
+
+    pub fn live(&self) -> bool {
+        self.live.load(Ordering::Relaxed)
+    }
+
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as _
+    }
+
+    // runs alongside the work it vouches for, so heartbeats stop when that work stalls the executor
+    pub async fn beat(&self) {
+        let heartbeat = Arc::new(AtomicU64::new(self.now()));
+        self.heartbeats
+            .lock()
+            .unwrap()
+            .push(Arc::downgrade(&heartbeat));
+        let mut interval = tokio::time::interval(self.interval / 2);
+        loop {
+            interval.tick().await;
+            heartbeat.store(self.now(), Ordering::Relaxed);
+        }
+    }
+
+    // runs on a dedicated thread, so it keeps checking while the executor stalls,
+    // and turns live again once every heartbeat is back
+    pub fn spawn(self: &Arc<Self>) -> thread::JoinHandle<()> {
+        let this = self.clone();
+        thread::spawn(move || {
+            loop {
+                thread::sleep(this.interval);
+                let oldest = {
+                    let mut heartbeats = this.heartbeats.lock().unwrap();
+                    heartbeats.retain(|heartbeat| heartbeat.strong_count() > 0);
+                    heartbeats
+                        .iter()
+                        .filter_map(Weak::upgrade)
+                        .map(|heartbeat| heartbeat.load(Ordering::Relaxed))
+                        .min()
+                };
+                let Some(oldest) = oldest else {
+                    continue;
+                };
+                let elapsed = this
+                    .epoch
+                    .elapsed()
+                    .saturating_sub(Duration::from_millis(oldest));
+                let live = elapsed <= this.interval;
+                if this.live.swap(live, Ordering::Relaxed) != live {
+                    if live {
+                        tracing::info!("watchdog recovered");
+                    } else {
+                        tracing::error!(?elapsed, "watchdog timeout");
+                    }
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,29 @@
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+
+// a daily range of local time, wrapping around midnight if `start` is after `end`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Window {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    // the days on which the range starts
+    pub weekdays: Option<Vec<Weekday>>,
+}
+
+impl Window {
+    pub fn contains(&self, now: NaiveDateTime) -> bool {
+        let time = now.time();
+        let (weekday, within) = if self.start <= self.end {
+            (now.weekday(), self.start <= time && time < self.end)
+        } else if self.start <= time {
+            (now.weekday(), true)
+        } else {
+            (now.weekday().pred(), time < self.end)
+        };
+        within
+            && self
+                .weekdays
+                .as_ref()
+                .is_none_or(|weekdays| weekdays.contains(&weekday))
+    }
+}
@@ -0,0 +1,337 @@
+use std::process::Command;
+
+fn healthzd() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_healthzd"))
+}
+
+#[test]
+fn test_check() {
+    let temp = tempfile::tempdir().unwrap();
+    let config = temp.path().join("healthzd.toml");
+    std::fs::write(
+        &config,
+        r#"
+[[target]]
+name = "up"
+readiness_probe = { exec = { command = ["true"] } }
+
+[[target]]
+name = "down"
+liveness_probe = { exec = { command = ["false"] }, failure_threshold = 3 }
+"#,
+    )
+    .unwrap();
+
+    let output = healthzd()
+        .arg("--check")
+        .arg("--config")
+        .arg(&config)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{stdout}");
+    let table = stdout
+        .lines()
+        .skip_while(|line| !line.starts_with("TARGET"))
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        table,
+        [
+            ["TARGET", "PROBE", "RESULT"],
+            ["up", "readiness", "PASS"],
+            ["down", "liveness", "FAIL"],
+        ],
+    );
+
+    std::fs::write(
+        &config,
+        "[[target]]\nname = \"broken\"\n[target.liveness_probe]\n",
+    )
+    .unwrap();
+    let output = healthzd()
+        .arg("--check")
+        .arg("--config")
+        .arg(&config)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("TARGET"));
+}
+
+#[test]
+fn test_validate() {
+    let temp = tempfile::tempdir().unwrap();
+    let config = temp.path().join("healthzd.toml");
+    let marker = temp.path().join("marker");
+    let validate = |data: &str| {
+        std::fs::write(&config, data).unwrap();
+        let output = healthzd()
+            .arg("--validate")
+            .arg("--config")
+            .arg(&config)
+            .output()
+            .unwrap();
+        (
+            output.status.code(),
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    let (code, stdout, _) = validate(&format!(
+        r#"
+[[target]]
+name = "app"
+liveness_probe = {{ exec = {{ command = ["touch", "{}"] }} }}
+readiness_probe = {{ http_get = {{ port = 8080, path = "/ready" }}, success_threshold = 2 }}
+"#,
+        marker.display(),
+    ));
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "1 targets are valid\n");
+    // nothing is run
+    assert!(!marker.exists());
+
+    for (data, message) in [
+        (
+            r#"[[target]]
+name = "app"
+liveness_probe = { exec = { command = [] } }"#,
+            ": liveness_probe: invalid length 0, expected one or more",
+        ),
+        (
+            r#"[[target]]
+name = "app"
+readiness_probe = { http_get = { path = "no slash" } }"#,
+            "readiness_probe: ",
+        ),
+        (
+            r#"[[target]]
+name = "app"
+readiness_probe = { exec = { command = ["true"] }, failure_threshold = 0 }"#,
+            "readiness_probe: invalid value: integer `0`, expected a positive failure_threshold",
+        ),
+        (
+            r#"[[target]]
+name = "app"
+liveness_probe = { exec = { command = ["true"] }, success_threshold = 2 }"#,
+            "success_threshold of liveness_probe must be 1",
+        ),
+        ("[[target]]\nname = ", "failed to parse "),
+    ] {
+        let (code, _, stderr) = validate(data);
+        assert_eq!(code, Some(1), "{data}");
+        assert!(stderr.contains(message), "{stderr}");
+    }
+
+    // reported like a broken config, rather than as a usage error
+    let output = healthzd()
+        .arg("--validate")
+        .arg("--target")
+        .arg(r#"{"name": "app", "liveness_probe": {}}"#)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("--target: liveness_probe: "), "{stderr}");
+}
+
+#[test]
+fn test_dedicated_binds_only() {
+    use std::io::{Read, Write};
+
+    let port = || {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    };
+    let (live, ready) = (port(), port());
+    let mut child = healthzd()
+        .arg("--liveness-bind")
+        .arg(live.to_string())
+        .arg("--readiness-bind")
+        .arg(ready.to_string())
+        .spawn()
+        .unwrap();
+    let get = |addr, path: &str| {
+        for _ in 0..50 {
+            if let Ok(mut stream) = std::net::TcpStream::connect(addr) {
+                write!(
+                    stream,
+                    "GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+                return response;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        panic!("{addr} never came up");
+    };
+
+    let responses = [get(live, "/live"), get(ready, "/ready")];
+    child.kill().unwrap();
+    child.wait().unwrap();
+    for response in responses {
+        assert!(response.starts_with("HTTP/1.1 200 "), "{response}");
+    }
+}
+
+#[test]
+fn test_bench() {
+    let temp = tempfile::tempdir().unwrap();
+    let config = temp.path().join("healthzd.toml");
+    std::fs::write(
+        &config,
+        r#"
+[[target]]
+name = "app"
+readiness_probe = { exec = { command = ["true"] } }
+
+[[target]]
+name = "both"
+liveness_probe = { exec = { command = ["false"] } }
+readiness_probe = { exec = { command = ["true"] } }
+"#,
+    )
+    .unwrap();
+    let bench = |args: &[&str]| {
+        let output = healthzd()
+            .args(["--log-level", "error", "bench", "--probe"])
+            .arg(&config)
+            .args(args)
+            .output()
+            .unwrap();
+        (
+            output.status.code(),
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    let (code, stdout, _) = bench(&["--name", "app", "--count", "4"]);
+    assert_eq!(code, Some(0));
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines[..2], ["count: 4", "success: 100.0%"], "{stdout}");
+    assert_eq!(
+        lines[2..]
+            .iter()
+            .map(|line| line.split_once(": ").unwrap().0)
+            .collect::<Vec<_>>(),
+        ["min", "p50", "p95", "max"],
+    );
+
+    let (code, stdout, _) = bench(&["--name", "both", "--kind", "liveness", "--count", "2"]);
+    assert_eq!(code, Some(0));
+    assert!(stdout.starts_with("count: 2\nsuccess: 0.0%\n"), "{stdout}");
+
+    // picks a probe only when there is one to pick
+    let (code, _, stderr) = bench(&["--name", "both"]);
+    assert_eq!(code, Some(1));
+    assert!(stderr.contains("more than one"), "{stderr}");
+    let (code, _, stderr) = bench(&["--name", "missing"]);
+    assert_eq!(code, Some(1));
+    assert!(stderr.contains("no target named missing"), "{stderr}");
+
+    let (code, stdout, _) = bench(&["--name", "app", "--count", "0"]);
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "");
+}
+
+#[test]
+fn test_target_name_prefix() {
+    use std::io::{Read, Write};
+
+    let temp = tempfile::tempdir().unwrap();
+    let token = temp.path().join("token");
+    std::fs::write(&token, "secret\n").unwrap();
+    let config = temp.path().join("healthzd.toml");
+    std::fs::write(
+        &config,
+        r#"
+[[target]]
+name = "db"
+readiness_probe = { exec = { command = ["true"] } }
+"#,
+    )
+    .unwrap();
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let mut child = healthzd()
+        .arg("--bind")
+        .arg(addr.to_string())
+        .args(["--target-name-prefix", "team-a", "--target"])
+        .arg(r#"{"name":"app","readiness_probe":{"external":{"ttl_seconds":60}}}"#)
+        .arg("--config")
+        .arg(&config)
+        .arg("--admin-token-file")
+        .arg(&token)
+        .spawn()
+        .unwrap();
+    let request = |request: &str| {
+        for _ in 0..50 {
+            if let Ok(mut stream) = std::net::TcpStream::connect(addr) {
+                stream.write_all(request.as_bytes()).unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+                return response;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        panic!("{addr} never came up");
+    };
+    let get = |path: &str| {
+        request(&format!(
+            "GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+        ))
+    };
+    let put = |path: &str, body: &str| {
+        request(&format!(
+            "PUT {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\
+             Authorization: Bearer secret\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\n\r\n{body}",
+            body.len(),
+        ))
+    };
+
+    let responses = [
+        get("/status"),
+        get("/metrics"),
+        put("/probe/team-a.app/status", r#"{"readiness":true}"#),
+        put("/probe/app/status", r#"{"readiness":true}"#),
+    ];
+    child.kill().unwrap();
+    child.wait().unwrap();
+    let [status, metrics, prefixed, bare] = responses;
+    assert!(status.contains(r#""team-a.app""#), "{status}");
+    assert!(status.contains(r#""team-a.db""#), "{status}");
+    assert!(metrics.contains(r#"name="team-a.app""#), "{metrics}");
+    assert!(metrics.contains(r#"name="team-a.db""#), "{metrics}");
+    assert!(prefixed.starts_with("HTTP/1.1 204 "), "{prefixed}");
+    assert!(bare.starts_with("HTTP/1.1 404 "), "{bare}");
+
+    // a name taken by both --target and --config is reported as prefixed, with or without --validate
+    for validate in [true, false] {
+        let mut command = healthzd();
+        if validate {
+            command.arg("--validate");
+        } else {
+            command.args(["--bind", "127.0.0.1:0"]);
+        }
+        let output = command
+            .args(["--target-name-prefix", "team-a", "--target"])
+            .arg(r#"{"name":"db","readiness_probe":{"exec":{"command":["true"]}}}"#)
+            .arg("--config")
+            .arg(&config)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("duplicate target team-a.db"), "{stderr}");
+    }
+}